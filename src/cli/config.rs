@@ -0,0 +1,193 @@
+//! Settings from the environment and the config file, see
+//! `resolve_settings`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::{Arguments, ProgressFormat};
+
+
+/// Prefix of the environment variables of the settings, e.g.
+/// `ZENODO_DL_CONCURRENCY`
+const ENV_PREFIX: &str = "ZENODO_DL_";
+
+
+/// Contents of the config file, keys named like the flags they stand for
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct Config {
+    token: Option<String>,
+    base_url: Option<String>,
+    concurrency: Option<usize>,
+    retries: Option<u32>,
+    /// globs like `--include`, used unless the command line has some
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    progress_format: Option<String>,
+    /// keys this version does not know, warned about
+    #[serde(flatten)]
+    unknown: BTreeMap<String, toml::Value>,
+}
+
+impl Config {
+    /// Keys of the file that are not settings
+    pub(crate) fn unknown_keys(&self) -> Vec<&str>
+    {
+        return self.unknown.keys().map(|key| key.as_str()).collect();
+    }
+}
+
+
+/// `~/.config/zenodo-dl/config.toml`, or below `$XDG_CONFIG_HOME` if set
+pub(crate) fn default_config_path() -> Option<PathBuf>
+{
+    let config_home: PathBuf = match std::env::var_os("XDG_CONFIG_HOME").filter(|home| !home.is_empty()) {
+        Some(config_home) => PathBuf::from(config_home),
+        None => PathBuf::from(std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?)
+            .join(".config")
+    };
+    return Some(config_home.join("zenodo-dl").join("config.toml"));
+}
+
+/// Reads the config file at `path`, or at the default path if `None`,
+/// where a missing file is no error
+pub(crate) fn load_config(path: Option<&str>) -> Result<Config, String>
+{
+    let (path, required): (PathBuf, bool) = match path {
+        Some(path) => (PathBuf::from(path), true),
+        None => match default_config_path() {
+            Some(path) => (path, false),
+            None => return Ok(Config::default())
+        }
+    };
+    let content: String = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound && !required => return Ok(Config::default()),
+        Err(err) => return Err(format!("could not read the config file {}: {}", path.display(), err))
+    };
+    return toml::from_str(&content)
+        .map_err(|err| format!("invalid config file {}: {}", path.display(), err.message()));
+}
+
+
+/// Value of a setting not given as flag: from its environment variable,
+/// else from the config file, else `None` to keep the default
+fn pick<T: FromStr>(key: &str, given: bool, env: &dyn Fn(&str) -> Option<String>, config: Option<T>)
+    -> Result<Option<T>, String>
+{
+    if given {
+        return Ok(None);
+    }
+    let variable: String = format!("{}{}", ENV_PREFIX, key.to_uppercase());
+    return match env(&variable).filter(|value| !value.is_empty()) {
+        Some(value) => value.parse::<T>().map(Some)
+            .map_err(|_| format!("invalid value '{}' of {}", value, variable)),
+        None => Ok(config)
+    };
+}
+
+/// Globs of a setting not given as flag, the environment variable holding
+/// them comma-separated
+fn pick_globs(key: &str, given: bool, env: &dyn Fn(&str) -> Option<String>, config: Option<Vec<String>>)
+    -> Option<Vec<String>>
+{
+    if given {
+        return None;
+    }
+    return match env(&format!("{}{}", ENV_PREFIX, key.to_uppercase())).filter(|value| !value.is_empty()) {
+        Some(value) => Some(value.split(',').map(|glob| glob.trim().to_string())
+            .filter(|glob| !glob.is_empty()).collect()),
+        None => config
+    };
+}
+
+/// Whether `base_url` is Zenodo itself (`Some(false)`) or its sandbox
+/// (`Some(true)`), `None` for other instances
+fn zenodo_instance(base_url: &str) -> Option<bool>
+{
+    let host: &str = base_url.split_once("://").map_or(base_url, |(_, rest)| rest)
+        .split(['/', ':', '?']).next().unwrap_or_default();
+    return match host.to_lowercase().as_str() {
+        "zenodo.org" | "www.zenodo.org" => Some(false),
+        "sandbox.zenodo.org" => Some(true),
+        _ => None
+    };
+}
+
+/// Reads the token from a credentials file, without the line break most
+/// of them end in
+fn read_token_file(path: &str) -> Result<String, String>
+{
+    let content: String = fs::read_to_string(path)
+        .map_err(|err| format!("could not read the token file {}: {}", path, err))?;
+    let token: &str = content.trim();
+    if token.is_empty() {
+        return Err(format!("the token file {} is empty", path));
+    }
+    return Ok(token.to_string());
+}
+
+/// The token unless given with `--token`: read from `--token-file`, else
+/// from `ZENODO_DL_TOKEN`, else from `ZENODO_TOKEN` or
+/// `ZENODO_SANDBOX_TOKEN` if the base URL is Zenodo or its sandbox, else
+/// from the config file. The Zenodo variables are not sent to other
+/// instances, which have tokens of their own.
+fn resolve_token(args: &Arguments, given: &dyn Fn(&str) -> bool, env: &dyn Fn(&str) -> Option<String>,
+    config: Option<String>) -> Result<Option<String>, String>
+{
+    if given("token") {
+        return Ok(None);
+    }
+    if let Some(path) = args.token_file.as_deref() {
+        return read_token_file(path).map(Some);
+    }
+    let variable: Option<&str> = match zenodo_instance(&args.base_url) {
+        Some(false) => Some("ZENODO_TOKEN"),
+        Some(true) => Some("ZENODO_SANDBOX_TOKEN"),
+        None => None
+    };
+    let from_env: Option<String> = [Some("ZENODO_DL_TOKEN"), variable].into_iter().flatten()
+        .filter_map(env)
+        .map(|token| token.trim().to_string())
+        .find(|token| !token.is_empty());
+    return Ok(from_env.or(config));
+}
+
+/// Settles the settings of `args` that can also come from the environment
+/// or the config file. A flag on the command line (`given` is true for its
+/// id) wins over the environment variable `ZENODO_DL_<KEY>`, which wins
+/// over the config file, which wins over the default of the flag.
+pub(crate) fn resolve_settings(args: &mut Arguments, given: &dyn Fn(&str) -> bool,
+    env: &dyn Fn(&str) -> Option<String>, config: Config) -> Result<(), String>
+{
+    if let Some(base_url) = pick("base_url", given("base_url"), env, config.base_url)? {
+        args.base_url = base_url;
+    }
+    if let Some(token) = resolve_token(args, given, env, config.token)? {
+        args.token = Some(token);
+    }
+    if let Some(concurrency) = pick("concurrency", given("concurrency"), env, config.concurrency)? {
+        args.concurrency = concurrency;
+    }
+    if let Some(retries) = pick("retries", given("retries"), env, config.retries)? {
+        args.retries = retries;
+    }
+    if let Some(include) = pick_globs("include", given("include"), env, config.include) {
+        args.include = include;
+    }
+    if let Some(exclude) = pick_globs("exclude", given("exclude"), env, config.exclude) {
+        args.exclude = exclude;
+    }
+    // bars cannot be swapped for events where the flags rule them out
+    let bars_only: bool = args.interactive || args.no_progress;
+    if let Some(format) = pick("progress_format", given("progress_format") || bars_only, env,
+        config.progress_format)? {
+        args.progress_format = <ProgressFormat as ValueEnum>::from_str(&format, true)
+            .map_err(|_| format!("invalid progress format '{}', expected bars or jsonl", format))?;
+    }
+    return Ok(());
+}
@@ -0,0 +1,348 @@
+//! The command line program run against a mock Zenodo: where its settings
+//! come from, its exit codes and what it prints.
+#![allow(clippy::needless_return)]
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use md5::{Digest, Md5};
+use serde_json::{json, Value};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+
+/// Exit codes as listed in the help of the program
+const EXIT_INVALID_ARGUMENTS: i32 = 2;
+const EXIT_NOT_FOUND: i32 = 3;
+
+
+/// The program with no settings but those of `config_home`, which holds
+/// no config file unless the test writes one
+fn zenodo_dl(config_home: &Path) -> Command
+{
+    let mut command = Command::new(env!("CARGO_BIN_EXE_zenodo_dl"));
+    for (variable, _) in std::env::vars_os() {
+        if variable.to_string_lossy().starts_with("ZENODO_") {
+            command.env_remove(variable);
+        }
+    }
+    command.env("XDG_CONFIG_HOME", config_home).env("NO_COLOR", "1");
+    return command;
+}
+
+fn run(command: &mut Command) -> Output
+{
+    let output: Output = command.output().expect("the program runs");
+    return output;
+}
+
+fn stdout(output: &Output) -> String
+{
+    return String::from_utf8_lossy(&output.stdout).to_string();
+}
+
+fn printed(output: &Output) -> String
+{
+    return format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+}
+
+/// Writes the config file read by default
+fn write_config(config_home: &Path, content: &str)
+{
+    let folder = config_home.join("zenodo-dl");
+    fs::create_dir_all(&folder).unwrap();
+    fs::write(folder.join("config.toml"), content).unwrap();
+}
+
+
+fn md5_hex(data: &[u8]) -> String
+{
+    return Md5::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect();
+}
+
+/// Zenodo serving record `id` with `files`
+async fn zenodo(id: &str, files: &[(&str, &[u8])]) -> MockServer
+{
+    let server: MockServer = MockServer::start().await;
+    let base_url: String = server.uri();
+    let entries: Vec<Value> = files.iter().map(|(key, data)| json!({
+        "key": key,
+        "size": data.len(),
+        "checksum": format!("md5:{}", md5_hex(data)),
+        "links": { "self": format!("{}/api/records/{}/files/{}/content", base_url, id, key) },
+    })).collect();
+    respond(&server, &format!("/api/records/{}", id), 200, json!({
+        "id": id.parse::<u64>().unwrap(),
+        "updated": "2024-02-03T04:05:06+00:00",
+        "links": { "self_html": format!("{}/records/{}", base_url, id) },
+        "metadata": { "title": "Test record", "publication_date": "2024-01-01" },
+    })).await;
+    respond(&server, &format!("/api/records/{}/files", id), 200, json!({ "enabled": true, "entries": entries }))
+        .await;
+    for (key, data) in files.iter() {
+        Mock::given(method("GET")).and(path(format!("/api/records/{}/files/{}/content", id, key)))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(data.to_vec()))
+            .mount(&server).await;
+    }
+    return server;
+}
+
+async fn respond(server: &MockServer, route: &str, status: u16, body: Value)
+{
+    Mock::given(method("GET")).and(path(route))
+        .respond_with(ResponseTemplate::new(status).set_body_json(body))
+        .mount(server).await;
+}
+
+async fn requests_for(server: &MockServer, route: &str) -> Vec<Request>
+{
+    let requests: Vec<Request> = server.received_requests().await.unwrap_or_default();
+    return requests.into_iter().filter(|request| request.url.path() == route).collect();
+}
+
+/// `Authorization` header of the requests for the files of record `id`
+async fn authorization(server: &MockServer, id: &str) -> Vec<Option<String>>
+{
+    return requests_for(server, &format!("/api/records/{}/files", id)).await.iter()
+        .map(|request| request.headers.get("authorization")
+            .map(|value| value.to_str().unwrap().to_string()))
+        .collect();
+}
+
+
+#[test]
+fn completions_are_generated_for_every_shell()
+{
+    let config_home = tempfile::tempdir().unwrap();
+    for shell in ["bash", "zsh", "fish", "powershell", "elvish"] {
+        let output: Output = run(zenodo_dl(config_home.path()).args(["completions", shell]));
+
+        assert!(output.status.success(), "{}: {}", shell, printed(&output));
+        let script: String = stdout(&output);
+        assert!(script.contains("verify"), "{}", shell);
+        // only some complete the values of the flags
+        if ["bash", "zsh", "fish"].contains(&shell) {
+            assert!(script.contains("verify-skip"), "{}", shell);
+        }
+    }
+}
+
+#[tokio::test]
+async fn flags_beat_environment_beat_config_file()
+{
+    let config_home = tempfile::tempdir().unwrap();
+    let [from_flag, from_env, from_config] = [
+        zenodo("1", &[("a.txt", b"a")]).await,
+        zenodo("1", &[("a.txt", b"a")]).await,
+        zenodo("1", &[("a.txt", b"a")]).await,
+    ];
+    write_config(config_home.path(), &format!("base_url = \"{}\"\n", from_config.uri()));
+    let list = || {
+        let mut command = zenodo_dl(config_home.path());
+        command.args(["list", "-r", "1", "--api-flavor", "zenodo"]);
+        return command;
+    };
+
+    assert!(run(&mut list()).status.success());
+    assert!(run(list().env("ZENODO_DL_BASE_URL", from_env.uri())).status.success());
+    assert!(run(list().env("ZENODO_DL_BASE_URL", from_env.uri()).args(["--base-url", &from_flag.uri()]))
+        .status.success());
+
+    for server in [&from_flag, &from_env, &from_config] {
+        assert_eq!(requests_for(server, "/api/records/1/files").await.len(), 1);
+    }
+}
+
+/// Flags and environment of a run, and the `Authorization` it sends
+type TokenCase<'a> = (&'a [&'a str], &'a [(&'a str, &'a str)], Option<&'a str>);
+
+#[tokio::test]
+async fn token_comes_from_the_most_specific_source()
+{
+    let config_home = tempfile::tempdir().unwrap();
+    write_config(config_home.path(), "token = \"from-config\"\n");
+    let token_file = config_home.path().join("token");
+    fs::write(&token_file, "from-file\n").unwrap();
+    let empty_config = config_home.path().join("empty.toml");
+    fs::write(&empty_config, "").unwrap();
+    let cases: [TokenCase; 5] = [
+        (&[], &[], Some("Bearer from-config")),
+        (&[], &[("ZENODO_DL_TOKEN", "from-env")], Some("Bearer from-env")),
+        (&["--token-file", token_file.to_str().unwrap()], &[("ZENODO_DL_TOKEN", "from-env")],
+            Some("Bearer from-file")),
+        (&["--token", "from-flag"], &[("ZENODO_DL_TOKEN", "from-env")], Some("Bearer from-flag")),
+        // the token of zenodo.org is not sent to another instance
+        (&["--config", empty_config.to_str().unwrap()], &[("ZENODO_TOKEN", "zenodo-only")], None),
+    ];
+
+    for (args, env, expected) in cases.into_iter() {
+        let server: MockServer = zenodo("2", &[("a.txt", b"a")]).await;
+        let output: Output = run(zenodo_dl(config_home.path())
+            .args(["list", "-r", "2", "--api-flavor", "zenodo", "--base-url", &server.uri()])
+            .args(args)
+            .envs(env.iter().copied()));
+
+        assert!(output.status.success(), "{:?}: {}", args, printed(&output));
+        assert_eq!(authorization(&server, "2").await, vec![expected.map(str::to_string)], "{:?} {:?}", args, env);
+        for token in ["from-config", "from-env", "from-file", "from-flag", "zenodo-only"] {
+            assert!(!printed(&output).contains(token), "{} printed", token);
+        }
+    }
+}
+
+#[tokio::test]
+async fn invalid_settings_are_invalid_arguments()
+{
+    let config_home = tempfile::tempdir().unwrap();
+    let server: MockServer = zenodo("3", &[("a.txt", b"a")]).await;
+    let list = || {
+        let mut command = zenodo_dl(config_home.path());
+        command.args(["list", "-r", "3", "--api-flavor", "zenodo", "--base-url", &server.uri()]);
+        return command;
+    };
+
+    let output: Output = run(list().env("ZENODO_DL_CONCURRENCY", "many"));
+    assert_eq!(output.status.code(), Some(EXIT_INVALID_ARGUMENTS));
+    assert!(printed(&output).contains("invalid value 'many' of ZENODO_DL_CONCURRENCY"), "{}", printed(&output));
+
+    let missing: String = config_home.path().join("missing.toml").to_str().unwrap().to_string();
+    let output: Output = run(list().args(["--config", &missing]));
+    assert_eq!(output.status.code(), Some(EXIT_INVALID_ARGUMENTS), "{}", printed(&output));
+
+    write_config(config_home.path(), "retries = \"often\"\n");
+    let output: Output = run(&mut list());
+    assert_eq!(output.status.code(), Some(EXIT_INVALID_ARGUMENTS), "{}", printed(&output));
+
+    // unknown keys are only warned about, e.g. those of a newer version
+    write_config(config_home.path(), "retries = 2\ncolour = \"red\"\n");
+    let output: Output = run(&mut list());
+    assert!(output.status.success(), "{}", printed(&output));
+    assert!(printed(&output).contains("unknown key 'colour'"), "{}", printed(&output));
+}
+
+#[tokio::test]
+async fn unavailable_records_exit_with_not_found()
+{
+    let config_home = tempfile::tempdir().unwrap();
+    let output_folder = tempfile::tempdir().unwrap();
+    let server: MockServer = MockServer::start().await;
+    respond(&server, "/api/records/4", 200, json!({
+        "id": 4,
+        "metadata": { "title": "Embargoed" },
+        "access": { "files": "restricted", "embargo": { "active": true, "until": "2030-01-01" } },
+    })).await;
+    respond(&server, "/api/records/4/files", 403, json!({ "status": 403, "message": "Permission denied." })).await;
+    let download = |args: &[&str]| {
+        let mut command = zenodo_dl(config_home.path());
+        command.args(["--api-flavor", "zenodo", "--base-url", &server.uri(), "-o",
+            output_folder.path().to_str().unwrap(), "--no-progress"]).args(args);
+        return run(&mut command);
+    };
+
+    let output: Output = download(&["-r", "4"]);
+    assert_eq!(output.status.code(), Some(EXIT_NOT_FOUND), "{}", printed(&output));
+    assert!(printed(&output).contains("2030-01-01"), "{}", printed(&output));
+
+    // a draft needs the token of its owner
+    let output: Output = download(&["-r", "5", "--draft"]);
+    assert_eq!(output.status.code(), Some(EXIT_NOT_FOUND), "{}", printed(&output));
+    let output: Output = download(&["-r", "5", "--draft", "--all-versions"]);
+    assert_eq!(output.status.code(), Some(EXIT_INVALID_ARGUMENTS), "{}", printed(&output));
+}
+
+#[tokio::test]
+async fn subcommands_accept_draft()
+{
+    let config_home = tempfile::tempdir().unwrap();
+    let output_folder = tempfile::tempdir().unwrap();
+    let server: MockServer = MockServer::start().await;
+
+    let output: Output = run(zenodo_dl(config_home.path()).args(["verify", "--draft", "-r", "6", "-o",
+        output_folder.path().to_str().unwrap(), "--api-flavor", "zenodo", "--base-url", &server.uri()]));
+
+    // parsed, and the draft is asked for
+    assert_eq!(output.status.code(), Some(EXIT_NOT_FOUND), "{}", printed(&output));
+    assert!(!requests_for(&server, "/api/records/6/draft/files").await.is_empty());
+}
+
+#[tokio::test]
+async fn report_keeps_the_order_of_the_record()
+{
+    let config_home = tempfile::tempdir().unwrap();
+    let output_folder = tempfile::tempdir().unwrap();
+    let large: Vec<u8> = vec![1u8; 30_000];
+    let medium: Vec<u8> = vec![2u8; 20_000];
+    let server: MockServer = zenodo("7", &[("large.bin", &large), ("small.bin", b"s"), ("medium.bin", &medium)]).await;
+
+    let output: Output = run(zenodo_dl(config_home.path()).args(["-r", "7", "-o",
+        output_folder.path().to_str().unwrap(), "--api-flavor", "zenodo", "--base-url", &server.uri(),
+        "--order", "smallest-first", "--concurrency", "2", "--output-format", "json", "--no-progress"]));
+
+    assert!(output.status.success(), "{}", printed(&output));
+    let report: Value = serde_json::from_str(&stdout(&output)).expect("the report is JSON");
+    let files: Vec<&str> = report["files"].as_array().expect("files of the report").iter()
+        .map(|file| file["filename"].as_str().unwrap())
+        .collect();
+    assert_eq!(files, vec!["large.bin", "small.bin", "medium.bin"]);
+    assert_eq!(fs::read(output_folder.path().join("medium.bin")).unwrap(), medium);
+}
+
+#[tokio::test]
+async fn info_shows_the_record_and_a_note_on_inaccessible_files()
+{
+    let config_home = tempfile::tempdir().unwrap();
+    let server: MockServer = zenodo("8", &[("a.bin", &[0u8; 1536]), ("b.txt", b"b\n")]).await;
+    respond(&server, "/api/records/9", 200, json!({
+        "id": 9,
+        "doi": "10.5281/zenodo.9",
+        "metadata": {
+            "title": "Embargoed",
+            "creators": [{ "name": "Doe, Jane" }, { "name": "Roe, Richard" }],
+            "license": { "id": "cc-by-4.0" },
+            "version": "2.0",
+        },
+        "access": { "files": "restricted", "embargo": { "active": true, "until": "2030-01-01" } },
+    })).await;
+    respond(&server, "/api/records/9/files", 403, json!({ "status": 403, "message": "Permission denied." })).await;
+    // files listed in the record, its files endpoint is not served
+    respond(&server, "/api/records/10", 200, json!({
+        "id": 10,
+        "metadata": { "title": "Listed" },
+        "files": { "enabled": true, "count": 3, "total_bytes": 3000 },
+    })).await;
+    let info = |args: &[&str]| run(zenodo_dl(config_home.path()).arg("info")
+        .args(["--api-flavor", "zenodo", "--base-url", &server.uri()]).args(args));
+
+    let output: Output = info(&["-r", "8"]);
+    assert!(output.status.success(), "{}", printed(&output));
+    assert!(stdout(&output).contains("Title:      Test record"), "{}", stdout(&output));
+    assert!(stdout(&output).contains("Published:  2024-01-01"), "{}", stdout(&output));
+    assert!(stdout(&output).contains("Files:      2 files"), "{}", stdout(&output));
+    assert!(!stdout(&output).contains("Access:"), "{}", stdout(&output));
+
+    let output: Output = info(&["-r", "8", "--json"]);
+    assert!(output.status.success(), "{}", printed(&output));
+    let record: Value = serde_json::from_str(&stdout(&output)).expect("the information is JSON");
+    assert_eq!((record["files"].as_u64(), record["size"].as_u64()), (Some(2), Some(1538)));
+
+    // the public metadata of an embargoed record is shown nonetheless
+    let output: Output = info(&["-r", "9"]);
+    assert!(output.status.success(), "{}", printed(&output));
+    for expected in ["Doe, Jane; Roe, Richard", "cc-by-4.0", "10.5281/zenodo.9", "Version:    2.0",
+        "Files:      not accessible", "Access:     record 9 is embargoed until 2030-01-01"] {
+        assert!(stdout(&output).contains(expected), "{}", stdout(&output));
+    }
+    let output: Output = info(&["-r", "9", "--json"]);
+    let record: Value = serde_json::from_str(&stdout(&output)).expect("the information is JSON");
+    assert_eq!(record["files"], Value::Null);
+    assert_eq!(record["creators"][1]["name"], "Roe, Richard");
+    assert_eq!(record["access"], "record 9 is embargoed until 2030-01-01 - its files cannot be downloaded before.");
+
+    let output: Output = info(&["-r", "10"]);
+    assert!(output.status.success(), "{}", printed(&output));
+    assert!(stdout(&output).contains("Files:      3 files"), "{}", stdout(&output));
+    // the access settings and the files come with the record
+    assert!(requests_for(&server, "/api/records/9/files").await.is_empty());
+    assert!(requests_for(&server, "/api/records/10/files").await.is_empty());
+}
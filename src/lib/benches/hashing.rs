@@ -0,0 +1,59 @@
+//! Throughput of hashing a local file read in chunks of different sizes,
+//! or from a memory map with the `mmap` feature:
+//! `cargo bench -p zenodo_dl_core --features mmap`
+#![allow(clippy::needless_return)]
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use zenodo_dl_core::{file_digest, ChecksumAlgorithm, HashReading, DEFAULT_HASH_CHUNK_SIZE};
+
+
+/// Large enough to be read from the disk rather than from the caches of
+/// the CPU, small enough to bench in a few seconds
+const FILE_SIZE: usize = 256 * 1024 * 1024;
+
+
+/// File of `FILE_SIZE` bytes of varying content in the temporary folder
+fn bench_file() -> PathBuf
+{
+    let path: PathBuf = std::env::temp_dir().join(format!("zenodo-dl-hashing-{}.bin", std::process::id()));
+    let mut file = fs::File::create(&path).expect("cannot create the file to hash");
+    let block: Vec<u8> = (0..1024 * 1024).map(|index: usize| (index * 31 % 251) as u8).collect();
+    for _ in 0..FILE_SIZE / block.len() {
+        file.write_all(&block).expect("cannot write the file to hash");
+    }
+    return path;
+}
+
+fn hashing(c: &mut Criterion)
+{
+    let path: PathBuf = bench_file();
+    let readings: Vec<(String, HashReading)> = vec![
+        ("64KiB".to_string(), HashReading::Chunked(64 * 1024)),
+        ("1MiB".to_string(), HashReading::Chunked(1024 * 1024)),
+        (format!("{}MiB", DEFAULT_HASH_CHUNK_SIZE / 1024 / 1024), HashReading::default()),
+        #[cfg(feature = "mmap")]
+        ("mmap".to_string(), HashReading::Mapped),
+    ];
+
+    let mut group = c.benchmark_group("hash_file");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(FILE_SIZE as u64));
+    for algorithm in [ChecksumAlgorithm::Md5, ChecksumAlgorithm::Sha256] {
+        for (name, reading) in readings.iter() {
+            group.bench_with_input(BenchmarkId::new(algorithm.name(), name), reading, |b, reading| {
+                b.iter(|| file_digest(&path, algorithm, *reading).expect("cannot hash the file"));
+            });
+        }
+    }
+    group.finish();
+    let _ = fs::remove_file(&path);
+}
+
+
+criterion_group!(benches, hashing);
+criterion_main!(benches);
@@ -0,0 +1,156 @@
+//! Synchronous versions of the functions of this crate, for programs that
+//! do not run an async runtime themselves. Enabled by the `blocking`
+//! feature.
+//!
+//! The functions run their async counterpart on a single-threaded tokio
+//! runtime shared by all calls, so that the connections pooled by the
+//! client of the options stay usable between calls. Like
+//! `reqwest::blocking`, they panic when called from within an async
+//! runtime.
+
+use std::future::Future;
+use std::sync::OnceLock;
+
+use tokio::runtime::Runtime;
+
+use crate::{CitationFormat, DownloadOptions, DownloadPlan, DownloadReport, FileInfo,
+    RecordMetadata, RecordOutcome, RecordSummary, RecordVersion, SearchResult, SearchSort,
+    VerificationReport, ZenodoDlError};
+
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+
+fn block_on<F: Future>(future: F) -> F::Output
+{
+    let runtime: &Runtime = RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to start the runtime of the blocking API")
+    });
+    return runtime.block_on(future);
+}
+
+
+/// Blocking version of [`crate::download_record`]
+pub fn download_record(identifier: &str, options: &DownloadOptions)
+    -> Result<DownloadReport, ZenodoDlError>
+{
+    return block_on(crate::download_record(identifier, options));
+}
+
+/// Blocking version of [`crate::download_records`]
+pub fn download_records(identifiers: &[&str], options: &DownloadOptions) -> Vec<RecordOutcome>
+{
+    return block_on(crate::download_records(identifiers, options));
+}
+
+/// Blocking version of [`crate::download_record_files`]
+pub fn download_record_files(identifier: &str, names: &[String], options: &DownloadOptions)
+    -> Result<DownloadReport, ZenodoDlError>
+{
+    return block_on(crate::download_record_files(identifier, names, options));
+}
+
+/// Blocking version of [`crate::download_record_to`]
+#[deprecated(since = "2.0.0", note = "set `target_folder` and `abort_on_error` in the options and use `download_record`")]
+#[allow(deprecated)]
+pub fn download_record_to(identifier: &str, target_folder: &str,
+    abort_on_error: &bool, options: &DownloadOptions) -> Result<DownloadReport, ZenodoDlError>
+{
+    return block_on(crate::download_record_to(identifier, target_folder, abort_on_error, options));
+}
+
+/// Blocking version of [`crate::download_records_to`]
+#[deprecated(since = "2.0.0", note = "set `target_folder` and `abort_on_error` in the options and use `download_records`")]
+#[allow(deprecated)]
+pub fn download_records_to(identifiers: &[&str], target_folder: &str,
+    abort_on_error: &bool, options: &DownloadOptions) -> Vec<RecordOutcome>
+{
+    return block_on(crate::download_records_to(identifiers, target_folder, abort_on_error, options));
+}
+
+/// Blocking version of [`crate::download_record_files_to`]
+#[deprecated(since = "2.0.0", note = "set `target_folder` and `abort_on_error` in the options and use `download_record_files`")]
+#[allow(deprecated)]
+pub fn download_record_files_to(identifier: &str, names: &[String], target_folder: &str,
+    abort_on_error: &bool, options: &DownloadOptions) -> Result<DownloadReport, ZenodoDlError>
+{
+    return block_on(crate::download_record_files_to(identifier, names, target_folder, abort_on_error, options));
+}
+
+/// Blocking version of [`crate::download_failed_files`]
+pub fn download_failed_files(options: &DownloadOptions) -> Result<DownloadReport, ZenodoDlError>
+{
+    return block_on(crate::download_failed_files(options));
+}
+
+/// Blocking version of [`crate::resume_download`]
+pub fn resume_download(options: &DownloadOptions) -> Result<DownloadReport, ZenodoDlError>
+{
+    return block_on(crate::resume_download(options));
+}
+
+/// Blocking version of [`crate::write_record_file`]
+pub fn write_record_file(identifier: &str, names: &[String], writer: &mut (dyn std::io::Write + Send),
+    options: &DownloadOptions) -> Result<u64, ZenodoDlError>
+{
+    return block_on(crate::write_record_file(identifier, names, writer, options));
+}
+
+/// Blocking version of [`crate::plan_record_download`]
+pub fn plan_record_download(identifier: &str, target_folder: &str,
+    options: &DownloadOptions) -> Result<DownloadPlan, ZenodoDlError>
+{
+    return block_on(crate::plan_record_download(identifier, target_folder, options));
+}
+
+/// Blocking version of [`crate::verify_local_files`]
+pub fn verify_local_files(identifier: &str, target_folder: &str,
+    options: &DownloadOptions) -> Result<VerificationReport, ZenodoDlError>
+{
+    return block_on(crate::verify_local_files(identifier, target_folder, options));
+}
+
+/// Blocking version of [`crate::get_record_metadata`]
+pub fn get_record_metadata(identifier: &str, options: &DownloadOptions)
+    -> Result<RecordMetadata, ZenodoDlError>
+{
+    return block_on(crate::get_record_metadata(identifier, options));
+}
+
+/// Blocking version of [`crate::get_citation`]
+pub fn get_citation(identifier: &str, format: CitationFormat, options: &DownloadOptions)
+    -> Result<String, ZenodoDlError>
+{
+    return block_on(crate::get_citation(identifier, format, options));
+}
+
+/// Blocking version of [`crate::list_record_files`]
+pub fn list_record_files(identifier: &str, options: &DownloadOptions)
+    -> Result<Vec<FileInfo>, ZenodoDlError>
+{
+    return block_on(crate::list_record_files(identifier, options));
+}
+
+/// Blocking version of [`crate::list_record_versions`]
+pub fn list_record_versions(identifier: &str, options: &DownloadOptions)
+    -> Result<Vec<RecordVersion>, ZenodoDlError>
+{
+    return block_on(crate::list_record_versions(identifier, options));
+}
+
+/// Blocking version of [`crate::list_community_records`]
+pub fn list_community_records(community: &str, options: &DownloadOptions)
+    -> Result<Vec<RecordSummary>, ZenodoDlError>
+{
+    return block_on(crate::list_community_records(community, options));
+}
+
+/// Blocking version of [`crate::search_records`]
+pub fn search_records(query: &str, sort: SearchSort, max_records: Option<usize>,
+    options: &DownloadOptions) -> Result<SearchResult, ZenodoDlError>
+{
+    return block_on(crate::search_records(query, sort, max_records, options));
+}
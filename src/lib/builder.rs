@@ -0,0 +1,383 @@
+//! Builder of `DownloadOptions`, e.g.
+//! `DownloadOptions::builder().target_folder("data").concurrency(4).build()?`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::{ApiFlavor, ChannelProgress, DownloadEvent, DownloadOptions, DownloadOrder, ExistingFileCheck,
+    ExistingFilePolicy, FileFilter, HashReading, HttpTransport, ProgressObserver, RetryPolicy, SubdirTemplate,
+    UnknownChecksumPolicy, UnsafePathPolicy, ZenodoDlError};
+
+
+/// Options set one by one, starting from the defaults of `DownloadOptions`.
+/// `build` sets up the HTTP client for the timeouts, proxy and User-Agent
+/// given, unless a client of its own was passed.
+#[derive(Clone, Default)]
+pub struct DownloadOptionsBuilder {
+    options: DownloadOptions,
+    client: Option<reqwest::Client>,
+}
+
+impl DownloadOptions {
+    pub fn builder() -> DownloadOptionsBuilder
+    {
+        return DownloadOptionsBuilder::default();
+    }
+}
+
+impl DownloadOptionsBuilder {
+    pub fn target_folder(mut self, target_folder: &str) -> Self
+    {
+        self.options.target_folder = target_folder.to_string();
+        return self;
+    }
+
+    pub fn abort_on_error(mut self, abort_on_error: bool) -> Self
+    {
+        self.options.abort_on_error = abort_on_error;
+        return self;
+    }
+
+    pub fn base_url(mut self, base_url: &str) -> Self
+    {
+        self.options.base_url = base_url.to_string();
+        return self;
+    }
+
+    pub fn api_flavor(mut self, api_flavor: ApiFlavor) -> Self
+    {
+        self.options.api_flavor = Some(api_flavor);
+        return self;
+    }
+
+    pub fn token(mut self, token: &str) -> Self
+    {
+        self.options.token = Some(token.to_string());
+        return self;
+    }
+
+    pub fn filter(mut self, filter: FileFilter) -> Self
+    {
+        self.options.filter = filter;
+        return self;
+    }
+
+    /// Leaves out files larger than `bytes`, reporting them as skipped
+    pub fn max_file_size(mut self, bytes: u64) -> Self
+    {
+        self.options.max_file_size = Some(bytes);
+        return self;
+    }
+
+    pub fn concurrency(mut self, concurrency: usize) -> Self
+    {
+        self.options.concurrency = concurrency;
+        return self;
+    }
+
+    /// Order in which the files of a record are downloaded
+    pub fn order(mut self, order: DownloadOrder) -> Self
+    {
+        self.options.order = order;
+        return self;
+    }
+
+    pub fn segments(mut self, segments: usize) -> Self
+    {
+        self.options.segments = segments;
+        return self;
+    }
+
+    /// Retries per file with the default backoff
+    pub fn retries(mut self, retries: u32) -> Self
+    {
+        self.options.retry_policy.retries = retries;
+        return self;
+    }
+
+    /// Downloads repeated when a file does not match its checksum
+    pub fn checksum_retries(mut self, checksum_retries: u32) -> Self
+    {
+        self.options.checksum_retries = checksum_retries;
+        return self;
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self
+    {
+        self.options.retry_policy = retry_policy;
+        return self;
+    }
+
+    pub fn progress(mut self, progress: Arc<dyn ProgressObserver>) -> Self
+    {
+        self.options.progress = progress;
+        return self;
+    }
+
+    /// Progress sent as events to `sender`, see `ChannelProgress` for what
+    /// happens while the channel is full
+    pub fn events(mut self, sender: mpsc::Sender<DownloadEvent>) -> Self
+    {
+        self.options.progress = Arc::new(ChannelProgress::new(sender));
+        return self;
+    }
+
+    pub fn cancel(mut self, cancel: CancellationToken) -> Self
+    {
+        self.options.cancel = cancel;
+        return self;
+    }
+
+    pub fn quiet(mut self, quiet: bool) -> Self
+    {
+        self.options.quiet = quiet;
+        return self;
+    }
+
+    pub fn resume(mut self, resume: bool) -> Self
+    {
+        self.options.resume = resume;
+        return self;
+    }
+
+    pub fn keep_partial(mut self, keep_partial: bool) -> Self
+    {
+        self.options.keep_partial = keep_partial;
+        return self;
+    }
+
+    pub fn follow_latest(mut self, follow_latest: bool) -> Self
+    {
+        self.options.follow_latest = follow_latest;
+        return self;
+    }
+
+    pub fn draft(mut self, draft: bool) -> Self
+    {
+        self.options.draft = draft;
+        return self;
+    }
+
+    pub fn unknown_checksum(mut self, policy: UnknownChecksumPolicy) -> Self
+    {
+        self.options.unknown_checksum = policy;
+        return self;
+    }
+
+    pub fn if_exists(mut self, policy: ExistingFilePolicy) -> Self
+    {
+        self.options.if_exists = policy;
+        return self;
+    }
+
+    pub fn verify_existing(mut self, check: ExistingFileCheck) -> Self
+    {
+        self.options.verify_existing = check;
+        return self;
+    }
+
+    /// Whether to ask the server if existing files changed by their ETag
+    /// instead of hashing them
+    pub fn etags(mut self, etags: bool) -> Self
+    {
+        self.options.etags = etags;
+        return self;
+    }
+
+    pub fn unsafe_paths(mut self, policy: UnsafePathPolicy) -> Self
+    {
+        self.options.unsafe_paths = policy;
+        return self;
+    }
+
+    pub fn write_buffer_size(mut self, bytes: usize) -> Self
+    {
+        self.options.write_buffer_size = bytes;
+        return self;
+    }
+
+    pub fn hash_reading(mut self, reading: HashReading) -> Self
+    {
+        self.options.hash_reading = reading;
+        return self;
+    }
+
+    pub fn limit_rate(mut self, bytes_per_second: u64) -> Self
+    {
+        self.options.limit_rate = bytes_per_second;
+        return self;
+    }
+
+    pub fn max_rate_limit_wait(mut self, wait: Duration) -> Self
+    {
+        self.options.max_rate_limit_wait = wait;
+        return self;
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self
+    {
+        self.options.connect_timeout = timeout;
+        return self;
+    }
+
+    pub fn read_timeout(mut self, timeout: Duration) -> Self
+    {
+        self.options.read_timeout = timeout;
+        return self;
+    }
+
+    pub fn stall_timeout(mut self, timeout: Duration) -> Self
+    {
+        self.options.stall_timeout = timeout;
+        return self;
+    }
+
+    pub fn max_reconnects(mut self, max_reconnects: u32) -> Self
+    {
+        self.options.max_reconnects = max_reconnects;
+        return self;
+    }
+
+    pub fn proxy(mut self, proxy: &str) -> Self
+    {
+        self.options.proxy = Some(proxy.to_string());
+        return self;
+    }
+
+    pub fn user_agent(mut self, user_agent: &str) -> Self
+    {
+        self.options.user_agent = user_agent.to_string();
+        return self;
+    }
+
+    /// Client used instead of one built from the options, e.g. with custom
+    /// certificates; timeouts, proxy and User-Agent are then up to it
+    pub fn client(mut self, client: reqwest::Client) -> Self
+    {
+        self.client = Some(client);
+        return self;
+    }
+
+    /// Transport sending the requests instead of the client, e.g. a mock
+    pub fn transport(mut self, transport: Arc<dyn HttpTransport>) -> Self
+    {
+        self.options.transport = Some(transport);
+        return self;
+    }
+
+    pub fn record_subdir(mut self, template: SubdirTemplate) -> Self
+    {
+        self.options.record_subdir = Some(template);
+        return self;
+    }
+
+    pub fn write_manifest(mut self, write_manifest: bool) -> Self
+    {
+        self.options.write_manifest = write_manifest;
+        return self;
+    }
+
+    pub fn save_metadata(mut self, save_metadata: bool) -> Self
+    {
+        self.options.save_metadata = save_metadata;
+        return self;
+    }
+
+    /// Whether to save the state of the download for resuming it or
+    /// retrying the failed files later
+    pub fn save_state(mut self, save_state: bool) -> Self
+    {
+        self.options.save_state = save_state;
+        return self;
+    }
+
+    /// Whether to store `.gz` files decompressed
+    pub fn decompress(mut self, decompress: bool) -> Self
+    {
+        self.options.decompress = decompress;
+        return self;
+    }
+
+    /// Whether to unpack downloaded archives into the target folder
+    pub fn extract(mut self, extract: bool) -> Self
+    {
+        self.options.extract = extract;
+        return self;
+    }
+
+    /// Whether to unpack each archive into a folder of its own
+    pub fn extract_subfolders(mut self, extract_subfolders: bool) -> Self
+    {
+        self.options.extract_subfolders = extract_subfolders;
+        return self;
+    }
+
+    /// Whether to delete archives once they were unpacked
+    pub fn remove_archives(mut self, remove_archives: bool) -> Self
+    {
+        self.options.remove_archives = remove_archives;
+        return self;
+    }
+
+    pub fn save_citation(mut self, save_citation: bool) -> Self
+    {
+        self.options.save_citation = save_citation;
+        return self;
+    }
+
+    pub fn preserve_mtime(mut self, preserve_mtime: bool) -> Self
+    {
+        self.options.preserve_mtime = preserve_mtime;
+        return self;
+    }
+
+    pub fn fsync(mut self, fsync: bool) -> Self
+    {
+        self.options.fsync = fsync;
+        return self;
+    }
+
+    pub fn ignore_disk_space(mut self, ignore_disk_space: bool) -> Self
+    {
+        self.options.ignore_disk_space = ignore_disk_space;
+        return self;
+    }
+
+    pub fn portable_names(mut self, portable_names: bool) -> Self
+    {
+        self.options.portable_names = portable_names;
+        return self;
+    }
+
+    pub fn name_replacement(mut self, replacement: char) -> Self
+    {
+        self.options.name_replacement = replacement;
+        return self;
+    }
+
+    pub fn flatten(mut self, flatten: bool) -> Self
+    {
+        self.options.flatten = flatten;
+        return self;
+    }
+
+    pub fn skip_complete_records(mut self, skip_complete_records: bool) -> Self
+    {
+        self.options.skip_complete_records = skip_complete_records;
+        return self;
+    }
+
+    /// The options, with the client passed or one built for them
+    pub fn build(self) -> Result<DownloadOptions, ZenodoDlError>
+    {
+        let mut options: DownloadOptions = self.options;
+        options.client = match self.client {
+            Some(client) => client,
+            None => options.build_client()?
+        };
+        return Ok(options);
+    }
+}
@@ -0,0 +1,350 @@
+//! Verification of file checksums as reported by Zenodo.
+
+use std::{fs, io};
+use std::io::Read;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use md5::{Md5, Digest};
+use serde::{Serialize, Deserialize};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+
+/// Default size of the reads when hashing a local file, large enough that
+/// fast disks are not held up by the number of reads
+pub const DEFAULT_HASH_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+
+/// Hash algorithms Zenodo/Invenio report checksums in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl ChecksumAlgorithm {
+    /// Parses the algorithm prefix of a checksum, e.g. `md5` in `md5:abc`
+    pub fn from_name(name: &str) -> Option<ChecksumAlgorithm>
+    {
+        return match name.to_ascii_lowercase().replace('-', "").as_str() {
+            "md5" => Some(ChecksumAlgorithm::Md5),
+            "sha1" => Some(ChecksumAlgorithm::Sha1),
+            "sha256" => Some(ChecksumAlgorithm::Sha256),
+            "sha512" => Some(ChecksumAlgorithm::Sha512),
+            _ => None
+        };
+    }
+
+    /// Lowercase name as used in checksum prefixes, e.g. `md5`
+    pub fn name(&self) -> &'static str
+    {
+        return match self {
+            ChecksumAlgorithm::Md5 => "md5",
+            ChecksumAlgorithm::Sha1 => "sha1",
+            ChecksumAlgorithm::Sha256 => "sha256",
+            ChecksumAlgorithm::Sha512 => "sha512",
+        };
+    }
+}
+
+
+/// A checksum as reported by Zenodo, e.g. `md5:0cc175b9c0f1b6a831c399e269772661`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checksum {
+    /// lowercase algorithm name, possibly one that is not supported
+    pub algorithm: String,
+    /// lowercase hex digest
+    pub value: String,
+}
+
+impl Checksum {
+    /// Parses `<algorithm>:<hex digest>`. Digests without an algorithm
+    /// prefix get the algorithm implied by their length. Returns `None` for
+    /// empty or malformed checksums, which therefore cannot be verified.
+    pub fn parse(raw: &str) -> Option<Checksum>
+    {
+        let trimmed: &str = raw.trim();
+        let (algorithm, value): (String, &str) = match trimmed.split_once(':') {
+            Some((algorithm, value)) => (algorithm.trim().to_ascii_lowercase(), value.trim()),
+            None => {
+                let implied: &str = match trimmed.len() {
+                    32 => "md5",
+                    40 => "sha1",
+                    64 => "sha256",
+                    128 => "sha512",
+                    _ => return None
+                };
+                (implied.to_string(), trimmed)
+            }
+        };
+        if algorithm.is_empty() || value.is_empty()
+            || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        return Some(Checksum {
+            algorithm,
+            value: value.to_ascii_lowercase(),
+        });
+    }
+
+    /// The algorithm, if it is one that can be verified
+    pub fn known_algorithm(&self) -> Option<ChecksumAlgorithm>
+    {
+        return ChecksumAlgorithm::from_name(&self.algorithm);
+    }
+}
+
+
+/// What to do with files whose checksum algorithm is not supported
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum UnknownChecksumPolicy {
+    /// warn and keep the file without verifying it
+    #[default]
+    SkipVerification,
+    /// warn and treat the file as failed
+    Fail,
+}
+
+
+/// How local files are read to hash them, see
+/// `DownloadOptions::hash_reading`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashReading {
+    /// reads of this many bytes into a buffer, 0 for
+    /// `DEFAULT_HASH_CHUNK_SIZE`
+    Chunked(usize),
+    /// maps the file into memory and hashes it in place; the file must not
+    /// be truncated meanwhile, which crashes the process on most systems
+    #[cfg(feature = "mmap")]
+    Mapped,
+}
+
+impl Default for HashReading {
+    fn default() -> Self
+    {
+        return HashReading::Chunked(DEFAULT_HASH_CHUNK_SIZE);
+    }
+}
+
+
+/// Outcome of comparing a file against its checksum
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Verification {
+    Match,
+    /// carries the digest actually computed
+    Mismatch(String),
+    Unsupported(String),
+}
+
+
+fn to_hex(bytes: &[u8]) -> String
+{
+    return bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+}
+
+
+/// Hashes `file`, passing the number of bytes hashed so far to `advance`
+/// after each chunk. Returns `None` as soon as `advance` returns false,
+/// e.g. because the download was cancelled.
+pub(crate) fn verify_checksum(file: &mut fs::File, filename: &str, checksum: &Checksum, reading: HashReading,
+    advance: &mut dyn FnMut(u64) -> bool) -> io::Result<Option<Verification>>
+{
+    let mut hasher: StreamHasher = match StreamHasher::for_checksum(checksum) {
+        Some(hasher) => hasher,
+        None => return Ok(Some(Verification::Unsupported(checksum.algorithm.clone())))
+    };
+    if !hash_file(file, filename, u64::MAX, &mut hasher, reading, advance)? {
+        return Ok(None);
+    }
+    return Ok(Some(hasher.verify(checksum)));
+}
+
+/// Hex digest of the file at `path` in `algorithm`, read as `reading`
+/// asks
+pub fn file_digest(path: &Path, algorithm: ChecksumAlgorithm, reading: HashReading) -> io::Result<String>
+{
+    let mut file: fs::File = fs::File::open(path)?;
+    let mut hasher: StreamHasher = StreamHasher::for_algorithm(algorithm);
+    hash_file(&mut file, &path.to_string_lossy(), u64::MAX, &mut hasher, reading, &mut |_| true)?;
+    return Ok(hasher.digest());
+}
+
+/// Feeds the first `length` bytes of `file` to `hasher`, see
+/// `verify_checksum`, and logs the throughput at debug level. Returns
+/// false if `advance` stopped it.
+pub(crate) fn hash_file(file: &mut fs::File, filename: &str, length: u64, hasher: &mut StreamHasher,
+    reading: HashReading, advance: &mut dyn FnMut(u64) -> bool) -> io::Result<bool>
+{
+    let throughput: Option<Throughput> = match reading {
+        HashReading::Chunked(chunk_size) => hash_chunked(&mut file.take(length), hasher, chunk_size, advance)?,
+        #[cfg(feature = "mmap")]
+        HashReading::Mapped => hash_mapped(file, length, hasher, advance)?
+    };
+    return Ok(match throughput {
+        Some(throughput) => {
+            throughput.log(filename);
+            true
+        },
+        None => false
+    });
+}
+
+/// Reads `reader` to its end in chunks of `chunk_size` bytes
+fn hash_chunked(reader: &mut dyn Read, hasher: &mut StreamHasher, chunk_size: usize,
+    advance: &mut dyn FnMut(u64) -> bool) -> io::Result<Option<Throughput>>
+{
+    let chunk_size: usize = if chunk_size == 0 { DEFAULT_HASH_CHUNK_SIZE } else { chunk_size };
+    let mut buffer: Vec<u8> = vec![0u8; chunk_size];
+    let mut throughput: Throughput = Throughput { bytes: 0u64, reading: None, hashing: Duration::ZERO };
+    let mut reading: Duration = Duration::ZERO;
+    loop {
+        let started: Instant = Instant::now();
+        let length: usize = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(length) => length,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err)
+        };
+        reading += started.elapsed();
+        let started: Instant = Instant::now();
+        hasher.update(&buffer[..length]);
+        throughput.hashing += started.elapsed();
+        throughput.bytes += length as u64;
+        if !advance(throughput.bytes) {
+            return Ok(None);
+        }
+    }
+    throughput.reading = Some(reading);
+    return Ok(Some(throughput));
+}
+
+/// Hashes the first `length` bytes of `file` from a memory map of it
+#[cfg(feature = "mmap")]
+fn hash_mapped(file: &fs::File, length: u64, hasher: &mut StreamHasher,
+    advance: &mut dyn FnMut(u64) -> bool) -> io::Result<Option<Throughput>>
+{
+    // empty files cannot be mapped everywhere
+    if file.metadata()?.len() == 0 {
+        return Ok(Some(Throughput { bytes: 0u64, reading: None, hashing: Duration::ZERO }));
+    }
+    let started: Instant = Instant::now();
+    // SAFETY: the map is only read, see `HashReading::Mapped` for files
+    // truncated by others meanwhile
+    let map: memmap2::Mmap = unsafe { memmap2::Mmap::map(file)? };
+    #[cfg(unix)]
+    let _ = map.advise(memmap2::Advice::Sequential);
+    let content: &[u8] = &map[..usize::try_from(length).unwrap_or(usize::MAX).min(map.len())];
+    let mut hashed: u64 = 0u64;
+    for chunk in content.chunks(DEFAULT_HASH_CHUNK_SIZE) {
+        hasher.update(chunk);
+        hashed += chunk.len() as u64;
+        if !advance(hashed) {
+            return Ok(None);
+        }
+    }
+    return Ok(Some(Throughput { bytes: hashed, reading: None, hashing: started.elapsed() }));
+}
+
+
+/// Where the time hashing a file went, telling whether the disk or the
+/// hash is the bottleneck
+struct Throughput {
+    bytes: u64,
+    /// waiting for reads, `None` if they happen while hashing a memory map
+    reading: Option<Duration>,
+    hashing: Duration,
+}
+
+impl Throughput {
+    fn log(&self, filename: &str)
+    {
+        let megabytes_per_second = |duration: Duration| self.bytes as f64 / 1e6 / duration.as_secs_f64().max(1e-9);
+        match self.reading {
+            Some(reading) => tracing::debug!("hashed {} ({} bytes) at {:.0} MB/s: reading at {:.0} MB/s, \
+                hashing at {:.0} MB/s", filename, self.bytes, megabytes_per_second(reading + self.hashing),
+                megabytes_per_second(reading), megabytes_per_second(self.hashing)),
+            None => tracing::debug!("hashed {} ({} bytes) at {:.0} MB/s from a memory map", filename, self.bytes,
+                megabytes_per_second(self.hashing))
+        };
+    }
+}
+
+
+fn compare(checksum: &Checksum, hash_str: String) -> Verification
+{
+    if hash_str == checksum.value {
+        return Verification::Match;
+    }
+    return Verification::Mismatch(hash_str);
+}
+
+
+/// Hashes a file while it is downloaded, so that it does not have to be
+/// read again for the verification
+pub(crate) enum StreamHasher {
+    Md5(Md5),
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl StreamHasher {
+    /// Hasher for the algorithm of `checksum`, `None` if it is not supported
+    pub(crate) fn for_checksum(checksum: &Checksum) -> Option<StreamHasher>
+    {
+        return Some(StreamHasher::for_algorithm(checksum.known_algorithm()?));
+    }
+
+    pub(crate) fn for_algorithm(algorithm: ChecksumAlgorithm) -> StreamHasher
+    {
+        return match algorithm {
+            ChecksumAlgorithm::Md5 => StreamHasher::Md5(Md5::new()),
+            ChecksumAlgorithm::Sha1 => StreamHasher::Sha1(Sha1::new()),
+            ChecksumAlgorithm::Sha256 => StreamHasher::Sha256(Sha256::new()),
+            ChecksumAlgorithm::Sha512 => StreamHasher::Sha512(Sha512::new()),
+        };
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8])
+    {
+        match self {
+            StreamHasher::Md5(hasher) => hasher.update(data),
+            StreamHasher::Sha1(hasher) => hasher.update(data),
+            StreamHasher::Sha256(hasher) => hasher.update(data),
+            StreamHasher::Sha512(hasher) => hasher.update(data),
+        };
+    }
+
+    /// Hex digest of everything hashed so far
+    pub(crate) fn digest(self) -> String
+    {
+        return match self {
+            StreamHasher::Md5(hasher) => to_hex(&hasher.finalize()),
+            StreamHasher::Sha1(hasher) => to_hex(&hasher.finalize()),
+            StreamHasher::Sha256(hasher) => to_hex(&hasher.finalize()),
+            StreamHasher::Sha512(hasher) => to_hex(&hasher.finalize()),
+        };
+    }
+
+    /// Compares everything hashed so far against `checksum`
+    pub(crate) fn verify(self, checksum: &Checksum) -> Verification
+    {
+        return compare(checksum, self.digest());
+    }
+}
+
+impl io::Write for StreamHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+    {
+        self.update(buf);
+        return Ok(buf.len());
+    }
+
+    fn flush(&mut self) -> io::Result<()>
+    {
+        return Ok(());
+    }
+}
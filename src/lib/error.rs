@@ -0,0 +1,346 @@
+//! Errors reported by the library.
+
+use std::{io, path::PathBuf, time::Duration};
+
+use thiserror::Error;
+
+use crate::transport::TransportError;
+
+
+/// Everything that can go wrong while resolving and downloading a record
+#[derive(Debug, Error)]
+pub enum ZenodoDlError {
+    /// the proxy URL is not valid, credentials in it are redacted
+    #[error("invalid proxy '{proxy}': {reason}")]
+    InvalidProxy {
+        proxy: String,
+        reason: String,
+    },
+
+    /// the HTTP client could not be set up, e.g. for an invalid proxy
+    #[error("could not set up the HTTP client: {source}")]
+    HttpClient {
+        #[source]
+        source: reqwest::Error,
+    },
+
+    /// the identifier is neither a record id, a record URL nor a DOI
+    #[error("{0}")]
+    InvalidIdentifier(String),
+
+    /// a DOI could not be resolved to a record
+    #[error("DOI {0} does not resolve to a Zenodo record")]
+    DoiNotResolved(String),
+
+    /// the request could not be sent or its response not be read
+    #[error("request for {what} failed: {source}")]
+    Request {
+        what: String,
+        #[source]
+        source: TransportError,
+    },
+
+    /// the server did not respond or stopped sending in time
+    #[error("request for {what} timed out: {source}")]
+    Timeout {
+        what: String,
+        #[source]
+        source: TransportError,
+    },
+
+    /// the connection failed while the content of a file was received
+    #[error("download of {filename} failed after {received} bytes: {source}")]
+    StreamInterrupted {
+        filename: String,
+        received: u64,
+        #[source]
+        source: TransportError,
+    },
+
+    /// no data of a file arrived for `waited`, see
+    /// `DownloadOptions::stall_timeout`
+    #[error("download of {filename} stalled after {received} bytes: no data for {}s", waited.as_secs())]
+    Stalled {
+        filename: String,
+        received: u64,
+        waited: Duration,
+    },
+
+    /// the record or file does not exist (HTTP 404)
+    #[error("request for {what} failed: not found ({})", format_status(404, message))]
+    NotFound {
+        what: String,
+        /// explanation from the error response, if the server gave one
+        message: Option<String>,
+    },
+
+    /// the record or file is restricted or needs another token (HTTP 401
+    /// or 403)
+    #[error("request for {what} failed: access denied ({})", format_status(status.as_u16(), message))]
+    AccessDenied {
+        what: String,
+        status: reqwest::StatusCode,
+        message: Option<String>,
+    },
+
+    /// the files of the record are under embargo, see `until`
+    #[error("record {record_id} is embargoed{}", until.as_ref().map(|until| format!(" until {}", until))
+        .unwrap_or_default())]
+    Embargoed {
+        record_id: String,
+        /// end of the embargo as given by the record (YYYY-MM-DD)
+        until: Option<String>,
+    },
+
+    /// the files of the record are only accessible with a token of a user
+    /// who was granted access
+    #[error("the files of record {record_id} are restricted")]
+    Restricted {
+        record_id: String,
+        /// page of the record, where access can be requested
+        record_url: Option<String>,
+    },
+
+    /// the draft of a record does not exist or is not shared with the
+    /// token, see `DownloadOptions::draft`
+    #[error("no draft of record {record_id} is accessible{}", if *with_token { " with the token" } else { " without a token" })]
+    DraftNotAccessible {
+        record_id: String,
+        /// whether a token was sent at all
+        with_token: bool,
+    },
+
+    /// the record was deleted and only its tombstone remains (HTTP 410)
+    #[error("request for {what} failed: removed ({})", format_status(410, message))]
+    Gone {
+        what: String,
+        message: Option<String>,
+    },
+
+    /// the server failed to answer the request (HTTP 5xx)
+    #[error("request for {what} failed: server error ({})", format_status(status.as_u16(), message))]
+    ServerError {
+        what: String,
+        status: reqwest::StatusCode,
+        message: Option<String>,
+    },
+
+    /// the server answered with another unexpected HTTP status
+    #[error("request for {what} failed ({})", format_status(status.as_u16(), message))]
+    HttpStatus {
+        what: String,
+        status: reqwest::StatusCode,
+        message: Option<String>,
+    },
+
+    /// the server kept answering with HTTP 429 Too Many Requests
+    #[error("request for {what} was rate limited by the server")]
+    RateLimited {
+        what: String,
+        /// wait asked for by the `Retry-After` header
+        retry_after: Option<Duration>,
+    },
+
+    /// a response body is not the expected JSON
+    #[error("could not parse {what}: {source}")]
+    JsonParse {
+        what: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    /// a file filter pattern is not a valid glob
+    #[error("invalid pattern '{pattern}': {reason}")]
+    InvalidPattern {
+        pattern: String,
+        reason: String,
+    },
+
+    /// a subdirectory template has unknown placeholders or path separators
+    #[error("invalid subdirectory template '{template}': {reason}")]
+    InvalidTemplate {
+        template: String,
+        reason: String,
+    },
+
+    /// a size such as `500M` could not be parsed
+    #[error("invalid size '{0}', expected e.g. 500M or 2GiB")]
+    InvalidSize(String),
+
+    /// the record does not list any files
+    #[error("record {0} has no files to download")]
+    NoFiles(String),
+
+    /// the server offers no citation in the format and none can be built
+    /// from the metadata
+    #[error("no {format} citation available for record {record_id}")]
+    CitationUnavailable {
+        record_id: String,
+        format: String,
+    },
+
+    /// a file key would be written outside the target folder
+    #[error("refusing to write '{filename}': {reason}")]
+    UnsafePath {
+        filename: String,
+        reason: String,
+    },
+
+    /// a file requested by name is not part of the record
+    #[error("record {record_id} has no file '{filename}'{}", format_suggestions(suggestions))]
+    FileNotInRecord {
+        record_id: String,
+        filename: String,
+        suggestions: Vec<String>,
+    },
+
+    /// a line of a checksum manifest is neither `<hash>  <name>` nor
+    /// `<hash> *<name>`
+    #[error("{}:{line}: {reason}", path.display())]
+    InvalidManifest {
+        path: PathBuf,
+        line: usize,
+        reason: String,
+    },
+
+    /// writing a single file to a stream needs exactly one file selected
+    #[error("{selected} files of record {record_id} are selected - writing to a stream takes exactly one")]
+    NotSingleFile {
+        record_id: String,
+        selected: usize,
+    },
+
+    /// a file exists already and the options forbid replacing it
+    #[error("{} exists already - refusing to replace it", path.display())]
+    FileExists {
+        path: PathBuf,
+    },
+
+    /// a folder to sync holds mostly files of no file of the record
+    #[error("only {matching} of {files} files in {} belong to the record - refusing to sync a folder it was not downloaded to", path.display())]
+    UnrelatedFolder {
+        path: PathBuf,
+        matching: usize,
+        files: usize,
+    },
+
+    /// the target folder holds no state of an earlier download to resume
+    /// or retry
+    #[error("{} does not exist - no earlier download to resume or retry", path.display())]
+    NoState {
+        path: PathBuf,
+    },
+
+    /// the state of an earlier download is unreadable or of an unknown
+    /// layout
+    #[error("could not read the state of the last download from {}: {reason}", path.display())]
+    InvalidState {
+        path: PathBuf,
+        reason: String,
+    },
+
+    /// a file matching its checksum is no valid gzip data and could not be
+    /// decompressed, see `DownloadOptions::decompress`
+    #[error("could not decompress {filename}: {reason}")]
+    Decompression {
+        filename: String,
+        reason: String,
+    },
+
+    /// a downloaded archive could not be unpacked
+    #[error("could not unpack {}: {reason}", path.display())]
+    InvalidArchive {
+        path: PathBuf,
+        reason: String,
+    },
+
+    /// the target folder has too little free space for the downloads
+    #[error("not enough free space in {}: {needed} bytes needed, {available} available", path.display())]
+    InsufficientSpace {
+        path: PathBuf,
+        needed: u64,
+        available: u64,
+    },
+
+    /// the stream a file was written to refused the data, e.g. a closed pipe
+    #[error("writing {filename} failed: {source}")]
+    WriteFailed {
+        filename: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// reading or writing a local file failed
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// a download has not the size announced by the server or the metadata
+    #[error("size mismatch for {filename}: expected {expected} bytes, got {got}")]
+    SizeMismatch {
+        filename: String,
+        expected: u64,
+        got: u64,
+    },
+
+    /// a downloaded file does not match the checksum from the metadata
+    #[error("checksum mismatch for {filename}: expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        filename: String,
+        expected: String,
+        actual: String,
+    },
+
+    /// the cancellation token of the options was cancelled
+    #[error("download of {what} cancelled")]
+    Cancelled {
+        what: String,
+    },
+
+    /// the checksum algorithm of a file is not supported and the options
+    /// ask to fail such files
+    #[error("unsupported checksum algorithm '{algorithm}' for {filename}")]
+    UnsupportedChecksum {
+        filename: String,
+        algorithm: String,
+    },
+}
+
+fn format_suggestions(suggestions: &[String]) -> String
+{
+    if suggestions.is_empty() {
+        return String::new();
+    }
+    return format!(" - did you mean: {}?", suggestions.join(", "));
+}
+
+/// `HTTP 403`, followed by the explanation of the server if any
+fn format_status(status: u16, message: &Option<String>) -> String
+{
+    return match message {
+        Some(message) => format!("HTTP {}: {}", status, message),
+        None => format!("HTTP {}", status)
+    };
+}
+
+impl ZenodoDlError {
+    /// Whether trying again later may succeed, e.g. after a dropped
+    /// connection, a server error or a corrupted transfer
+    pub fn is_retryable(&self) -> bool
+    {
+        return match self {
+            ZenodoDlError::Request { .. } => true,
+            ZenodoDlError::Timeout { .. } => true,
+            ZenodoDlError::StreamInterrupted { .. } => true,
+            ZenodoDlError::Stalled { .. } => true,
+            ZenodoDlError::ServerError { .. } => true,
+            ZenodoDlError::HttpStatus { status, .. } => status.is_server_error(),
+            ZenodoDlError::SizeMismatch { .. } => true,
+            ZenodoDlError::ChecksumMismatch { .. } => true,
+            _ => false
+        };
+    }
+}
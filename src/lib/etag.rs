@@ -0,0 +1,183 @@
+//! ETags of downloaded files, kept next to them so that later runs can ask
+//! the server with `If-None-Match` whether a file changed instead of
+//! hashing the local copy.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use serde::{Serialize, Deserialize};
+
+use crate::error::ZenodoDlError;
+use crate::transport::{HttpRequest, HttpResponse};
+use crate::{authorized_get, request_file, write_json, DownloadOptions, FileData, ETAGS_FILENAME};
+
+
+/// ETag of a file and the local copy it was received for. A copy changed
+/// since, e.g. by hand, no longer matches and is checked by its checksum.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct EtagEntry {
+    etag: String,
+    size: u64,
+    /// modification time of the local copy in nanoseconds since the epoch
+    modified: u64,
+}
+
+fn local_state(filepath: &Path) -> Option<(u64, u64)>
+{
+    let meta: fs::Metadata = fs::metadata(filepath).ok().filter(|meta| meta.is_file())?;
+    let modified: u64 = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos() as u64;
+    return Some((meta.len(), modified));
+}
+
+
+/// ETags of the files in a target folder, by local name
+pub(crate) struct EtagStore {
+    /// `None` if the options turned ETags off
+    path: Option<PathBuf>,
+    entries: Mutex<BTreeMap<String, EtagEntry>>,
+    changed: AtomicBool,
+    /// set once the server answered a conditional request in full for an
+    /// unchanged ETag, so that the run stops asking
+    unsupported: AtomicBool,
+}
+
+impl EtagStore {
+    /// The ETags saved in `target_folder`; none if the file is missing or
+    /// unreadable, in which case existing files are checked by checksum
+    pub(crate) fn load(target_folder: &str, options: &DownloadOptions) -> EtagStore
+    {
+        if !options.etags {
+            return EtagStore {
+                path: None,
+                entries: Mutex::new(BTreeMap::new()),
+                changed: AtomicBool::new(false),
+                unsupported: AtomicBool::new(true),
+            };
+        }
+        let path: PathBuf = Path::new(target_folder).join(ETAGS_FILENAME);
+        let entries: BTreeMap<String, EtagEntry> = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|err| {
+                tracing::debug!("ignoring {}: {}", path.display(), err);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new()
+        };
+        return EtagStore {
+            path: Some(path),
+            entries: Mutex::new(entries),
+            changed: AtomicBool::new(false),
+            unsupported: AtomicBool::new(false),
+        };
+    }
+
+    /// ETag saved for the local copy of `entry` at `filepath`, if the copy
+    /// is still the one it was saved for
+    fn lookup(&self, entry: &FileData, filepath: &Path) -> Option<String>
+    {
+        if self.path.is_none() || self.unsupported.load(Ordering::Relaxed) {
+            return None;
+        }
+        let (size, modified) = local_state(filepath)?;
+        let entries = self.entries.lock().ok()?;
+        return entries.get(&entry.local_name)
+            .filter(|saved| saved.size == size && saved.size == entry.size && saved.modified == modified)
+            .map(|saved| saved.etag.clone());
+    }
+
+    /// Remembers the ETag a file at `filepath` was downloaded with
+    pub(crate) fn record(&self, entry: &FileData, filepath: &Path, etag: Option<String>)
+    {
+        if self.path.is_none() {
+            return;
+        }
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        let saved: Option<EtagEntry> = etag.zip(local_state(filepath))
+            .map(|(etag, (size, modified))| EtagEntry { etag, size, modified });
+        let previous: Option<EtagEntry> = match saved.clone() {
+            Some(saved) => entries.insert(entry.local_name.clone(), saved),
+            None => entries.remove(&entry.local_name)
+        };
+        if previous != saved {
+            self.changed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Updates the saved ETag of `entry`, if any, to its local copy at
+    /// `filepath` after that copy was verified by its checksum, e.g. when
+    /// only its modification time changed
+    pub(crate) fn refresh(&self, entry: &FileData, filepath: &Path)
+    {
+        let etag: Option<String> = match self.entries.lock() {
+            Ok(entries) => entries.get(&entry.local_name).map(|saved| saved.etag.clone()),
+            Err(_) => return
+        };
+        if etag.is_some() {
+            self.record(entry, filepath, etag);
+        }
+    }
+
+    /// Writes the ETags back if any changed
+    pub(crate) fn save(&self) -> Result<(), ZenodoDlError>
+    {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        if !self.changed.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let entries = match self.entries.lock() {
+            Ok(entries) => entries,
+            Err(_) => return Ok(())
+        };
+        let value: serde_json::Value = serde_json::to_value(&*entries).map_err(|source|
+            ZenodoDlError::JsonParse { what: path.display().to_string(), source })?;
+        return write_json(path, &value);
+    }
+
+    /// Whether the server confirms the local copy of `entry` at `filepath`
+    /// unchanged by answering 304 Not Modified to its saved ETag. Any other
+    /// outcome, including errors, leaves the decision to the checksum.
+    pub(crate) async fn unchanged(&self, entry: &FileData, filepath: &Path,
+        options: &DownloadOptions) -> bool
+    {
+        let Some(etag) = self.lookup(entry, filepath) else {
+            return false;
+        };
+        let request: HttpRequest = authorized_get(&entry.url, options)
+            .header(reqwest::header::IF_NONE_MATCH, etag.as_str());
+        let response: HttpResponse = match request_file(request, &entry.filename, options).await {
+            Ok(response) => response,
+            Err(err) => {
+                tracing::debug!("conditional request for {} failed: {}", entry.filename, err);
+                return false;
+            }
+        };
+        if response.status == reqwest::StatusCode::NOT_MODIFIED {
+            return true;
+        }
+        // the full file in reply to an ETag that still matches means the
+        // server ignores the condition; the body is dropped unread
+        let current: Option<&str> = response.headers.get(reqwest::header::ETAG)
+            .and_then(|value| value.to_str().ok());
+        if response.status.is_success() && current == Some(etag.as_str()) {
+            tracing::debug!("server ignores If-None-Match - checking existing files by checksum");
+            self.unsupported.store(true, Ordering::Relaxed);
+        }
+        return false;
+    }
+}
+
+
+/// ETag of a response, if it has one
+pub(crate) fn response_etag(response: &HttpResponse) -> Option<String>
+{
+    return response.headers.get(reqwest::header::ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+}
@@ -0,0 +1,204 @@
+//! Progress as events sent over a tokio channel, for async consumers such
+//! as GUIs forwarding them to their UI thread.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use serde::{Serialize, Deserialize};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::error::ZenodoDlError;
+use crate::progress::ProgressObserver;
+
+
+/// An event sent by `ChannelProgress`, serialized tagged by its `event`
+/// field like `ProgressEvent`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum DownloadEvent {
+    /// the download of `files` files of a record starts
+    RecordStarted {
+        files: usize,
+    },
+    /// an attempt to download a file starts, again for each retry; `size`
+    /// is 0 if the record does not tell
+    FileStarted {
+        name: String,
+        size: u64,
+    },
+    /// `bytes` of the file of `total` bytes are present so far; `total` is
+    /// 0 while the size is unknown. Replaces the progress of the file held
+    /// back while the channel is full.
+    Progress {
+        name: String,
+        bytes: u64,
+        total: u64,
+    },
+    /// the file was downloaded and verified, or existed already
+    FileFinished {
+        name: String,
+    },
+    /// the file failed for good, after all retries
+    FileFailed {
+        name: String,
+        reason: String,
+    },
+    /// the download of the record is over
+    RecordFinished,
+}
+
+/// Queues `event` behind the events held back. Progress replaces the one
+/// of the same file among the progress last held back, so that at most one
+/// per file in flight is queued.
+fn hold_back(held_back: &mut VecDeque<DownloadEvent>, event: DownloadEvent)
+{
+    if let DownloadEvent::Progress { name, .. } = &event {
+        let queued = held_back.iter_mut().rev()
+            .take_while(|held| matches!(held, DownloadEvent::Progress { .. }))
+            .find(|held| matches!(held, DownloadEvent::Progress { name: held_name, .. } if held_name == name));
+        if let Some(queued) = queued {
+            *queued = event;
+            return;
+        }
+    }
+    held_back.push_back(event);
+}
+
+
+/// Sends the progress of downloads as `DownloadEvent`s to a channel, see
+/// `DownloadOptionsBuilder::events`.
+///
+/// Sending never waits for the receiver, so a slow consumer cannot slow
+/// down the downloads: while the channel is full, events are held back,
+/// then sent in order ahead of any later event once there is room again.
+/// Only the latest `Progress` of a file is held back. Events still held
+/// back when the record is finished are sent by a task of their own as
+/// soon as the receiver takes them. A closed receiver is ignored.
+pub struct ChannelProgress {
+    sender: mpsc::Sender<DownloadEvent>,
+    held_back: Arc<Mutex<VecDeque<DownloadEvent>>>,
+    /// size of each file in flight, for the `total` of its progress
+    sizes: Mutex<HashMap<String, u64>>,
+}
+
+impl ChannelProgress {
+    pub fn new(sender: mpsc::Sender<DownloadEvent>) -> ChannelProgress
+    {
+        return ChannelProgress {
+            sender,
+            held_back: Arc::new(Mutex::new(VecDeque::new())),
+            sizes: Mutex::new(HashMap::new()),
+        };
+    }
+
+    /// Sends `event` if there is room, after the events held back before
+    fn send(&self, event: DownloadEvent)
+    {
+        let Ok(mut held_back) = self.held_back.lock() else {
+            return;
+        };
+        while let Some(held) = held_back.pop_front() {
+            match self.sender.try_send(held) {
+                Ok(()) => continue,
+                Err(TrySendError::Full(held)) => {
+                    held_back.push_front(held);
+                    break;
+                },
+                Err(TrySendError::Closed(_)) => {
+                    held_back.clear();
+                    return;
+                }
+            };
+        }
+        if !held_back.is_empty() {
+            hold_back(&mut held_back, event);
+            return;
+        }
+        if let Err(TrySendError::Full(event)) = self.sender.try_send(event) {
+            hold_back(&mut held_back, event);
+        }
+    }
+
+    /// Leaves the events held back to a task waiting for room in the
+    /// channel; without a runtime to run it they are lost
+    fn flush(&self)
+    {
+        if self.held_back.lock().map_or(true, |held_back| held_back.is_empty()) {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            tracing::debug!("no runtime to send the progress events held back");
+            return;
+        };
+        let (sender, held_back) = (self.sender.clone(), Arc::clone(&self.held_back));
+        runtime.spawn(async move {
+            // events sent meanwhile queue up behind; the lock is held until
+            // the event is in the channel, so none of them can overtake it
+            while let Ok(permit) = sender.reserve().await {
+                let Ok(mut held_back) = held_back.lock() else {
+                    return;
+                };
+                let Some(event) = held_back.pop_front() else {
+                    return;
+                };
+                permit.send(event);
+            }
+        });
+    }
+
+    fn forget(&self, filename: &str)
+    {
+        if let Ok(mut sizes) = self.sizes.lock() {
+            sizes.remove(filename);
+        }
+    }
+}
+
+impl ProgressObserver for ChannelProgress {
+    fn download_started(&self, files: usize)
+    {
+        self.send(DownloadEvent::RecordStarted { files });
+    }
+
+    fn file_started(&self, filename: &str, size: u64)
+    {
+        if let Ok(mut sizes) = self.sizes.lock() {
+            sizes.insert(filename.to_string(), size);
+        }
+        self.send(DownloadEvent::FileStarted { name: filename.to_string(), size });
+    }
+
+    fn file_size_known(&self, filename: &str, size: u64)
+    {
+        if let Ok(mut sizes) = self.sizes.lock() {
+            sizes.insert(filename.to_string(), size);
+        }
+    }
+
+    fn bytes_advanced(&self, filename: &str, bytes: u64)
+    {
+        let total: u64 = match self.sizes.lock() {
+            Ok(sizes) => sizes.get(filename).copied().unwrap_or(0),
+            Err(_) => return
+        };
+        self.send(DownloadEvent::Progress { name: filename.to_string(), bytes, total });
+    }
+
+    fn file_finished(&self, filename: &str)
+    {
+        self.forget(filename);
+        self.send(DownloadEvent::FileFinished { name: filename.to_string() });
+    }
+
+    fn file_failed(&self, filename: &str, error: &ZenodoDlError)
+    {
+        self.forget(filename);
+        self.send(DownloadEvent::FileFailed { name: filename.to_string(), reason: error.to_string() });
+    }
+
+    fn download_finished(&self)
+    {
+        self.send(DownloadEvent::RecordFinished);
+        self.flush();
+    }
+}
@@ -0,0 +1,180 @@
+//! What happens to local copies of files that are downloaded again.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+
+use crate::checksum::Checksum;
+use crate::error::ZenodoDlError;
+use crate::gunzip;
+use crate::verify::LocalFileStatus;
+use crate::{io_error, local_file_status, DownloadOptions, FileData};
+
+
+/// What to do with a file that exists already in the target folder
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ExistingFilePolicy {
+    /// keep it if it matches its checksum (or has none), download it again
+    /// otherwise
+    #[default]
+    VerifySkip,
+    /// always download it again, replacing it once the download is verified
+    Overwrite,
+    /// always download it again, keeping the old copy as `<name>.old-N`
+    Rename,
+    /// refuse to download the record
+    Fail,
+}
+
+
+/// How closely an existing file is looked at before it is kept
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExistingFileCheck {
+    /// it must match its checksum, which means reading all of it; files
+    /// without a usable checksum are only checked for existence
+    #[default]
+    Checksum,
+    /// it must have the size listed in the record
+    Size,
+    /// it only has to exist
+    Existence,
+    /// the server answered that it did not change since it was downloaded,
+    /// see `DownloadOptions::etags`; as setting the same as `Checksum`
+    Etag,
+}
+
+
+/// Decision about the local copy of a single file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ExistingFile {
+    /// there is none, the file is downloaded
+    Missing,
+    /// it is kept after the given check and the file not downloaded
+    Keep(ExistingFileCheck),
+    /// the file is downloaded and replaces it, see `replace_file`
+    Replace,
+}
+
+
+/// Status of the local copy of `entry` at `filepath` as far as
+/// `options.verify_existing` looks, and the check that was actually done.
+/// Files failing the size check count as corrupt.
+pub(crate) fn existing_file_status(filepath: &Path, entry: &FileData, options: &DownloadOptions)
+    -> Result<(LocalFileStatus, ExistingFileCheck), ZenodoDlError>
+{
+    let check: ExistingFileCheck = options.verify_existing;
+    if !filepath.is_file() {
+        return Ok((LocalFileStatus::Missing, check));
+    }
+    let gunzipped: bool = gunzip::is_gunzipped(entry);
+    // the record only knows the size and checksum of the compressed file,
+    // and some records no size at all
+    let checksum: Option<Checksum> = match gunzipped {
+        true => gunzip::local_checksum(filepath, entry),
+        false => entry.checksum.clone()
+    };
+    return Ok(match check {
+        ExistingFileCheck::Size if gunzipped || entry.known_size().is_none() =>
+            (LocalFileStatus::Unverified, ExistingFileCheck::Existence),
+        ExistingFileCheck::Checksum | ExistingFileCheck::Etag => match local_file_status(filepath, &entry.filename,
+            checksum.as_ref(), options)?.0 {
+            LocalFileStatus::Unverified => (LocalFileStatus::Unverified, ExistingFileCheck::Existence),
+            status => (status, ExistingFileCheck::Checksum)
+        },
+        ExistingFileCheck::Size => {
+            let length: u64 = fs::metadata(filepath).map_err(io_error(filepath))?.len();
+            if length == entry.size {
+                (LocalFileStatus::Unverified, ExistingFileCheck::Size)
+            } else {
+                (LocalFileStatus::Corrupt, ExistingFileCheck::Size)
+            }
+        },
+        ExistingFileCheck::Existence => (LocalFileStatus::Unverified, ExistingFileCheck::Existence)
+    });
+}
+
+/// Decides what to do about the local copy of `entry` at `filepath` per
+/// `options.if_exists` and `options.verify_existing`. Nothing is deleted
+/// or renamed here.
+pub(crate) fn check_existing_file(filepath: &Path, entry: &FileData, options: &DownloadOptions)
+    -> Result<ExistingFile, ZenodoDlError>
+{
+    let filename: &str = &entry.filename;
+    if !filepath.is_file() {
+        return Ok(ExistingFile::Missing);
+    }
+    return match options.if_exists {
+        ExistingFilePolicy::VerifySkip => {
+            // files without a usable checksum are kept as they are
+            let (local_status, check) = existing_file_status(filepath, entry, options)?;
+            if local_status == LocalFileStatus::Corrupt {
+                // kept until the new download has been verified and replaces it
+                if check == ExistingFileCheck::Size {
+                    warning!(options, "incorrect size - {} will be downloaded again", filename);
+                } else {
+                    warning!(options, "incorrect checksum - {} will be downloaded again", filename);
+                }
+                Ok(ExistingFile::Replace)
+            } else {
+                status!(options, "{} downloaded already - skipping file", filename);
+                Ok(ExistingFile::Keep(check))
+            }
+        },
+        ExistingFilePolicy::Overwrite => {
+            status!(options, "{} exists - downloading it again", filename);
+            Ok(ExistingFile::Replace)
+        },
+        ExistingFilePolicy::Rename => {
+            status!(options, "{} exists - downloading it again, keeping the old copy", filename);
+            Ok(ExistingFile::Replace)
+        },
+        ExistingFilePolicy::Fail => Err(ZenodoDlError::FileExists { path: filepath.to_path_buf() })
+    };
+}
+
+/// First `<name>.old-N` next to `filepath` that is still free
+fn old_copy_path(filepath: &Path) -> PathBuf
+{
+    let mut index: u32 = 1;
+    loop {
+        let mut old_path = filepath.as_os_str().to_os_string();
+        old_path.push(format!(".old-{}", index));
+        let old_path: PathBuf = PathBuf::from(old_path);
+        if fs::symlink_metadata(&old_path).is_err() {
+            return old_path;
+        }
+        index += 1;
+    }
+}
+
+/// Moves a verified download to `filepath`, first moving an existing copy
+/// out of the way if `options.if_exists` asks to keep it
+pub(crate) fn replace_file(download_path: &Path, filepath: &Path, options: &DownloadOptions)
+    -> Result<(), ZenodoDlError>
+{
+    if options.if_exists == ExistingFilePolicy::Rename && filepath.is_file() {
+        let old_path: PathBuf = old_copy_path(filepath);
+        fs::rename(filepath, &old_path).map_err(io_error(filepath))?;
+        status!(options, "kept the previous {} as {}", filepath.display(), old_path.display());
+    }
+    return fs::rename(download_path, filepath).map_err(io_error(filepath));
+}
+
+/// Writes the file at `filepath` and its entry in its folder through to
+/// the disk, see `DownloadOptions::fsync`
+pub(crate) fn sync_to_disk(filepath: &Path) -> Result<(), ZenodoDlError>
+{
+    fs::File::options().write(true).open(filepath).and_then(|file| file.sync_all())
+        .map_err(io_error(filepath))?;
+    // the rename only lasts once the folder is synced, which only unix can
+    // open for it
+    #[cfg(unix)]
+    {
+        let folder: &Path = filepath.parent().filter(|folder| !folder.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        fs::File::open(folder).and_then(|folder| folder.sync_all()).map_err(io_error(folder))?;
+    }
+    return Ok(());
+}
@@ -0,0 +1,285 @@
+//! Unpacking downloaded archives, see `DownloadOptions::extract`.
+
+use std::fs;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+
+use crate::error::ZenodoDlError;
+use crate::io_error;
+use crate::paths::{is_inside, unsafe_key_reason};
+use crate::DownloadOptions;
+
+
+/// Archive formats that can be unpacked
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+}
+
+/// Extensions of archives and compressed files that cannot be unpacked,
+/// which are left in place with a note
+const UNSUPPORTED_EXTENSIONS: [&str; 8] = [".rar", ".7z", ".gz", ".bz2", ".xz", ".tbz2", ".txz", ".zst"];
+
+
+/// What `DownloadOptions::extract` did with a downloaded file
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Extraction {
+    /// the files of the archive were unpacked into `folder`, relative to
+    /// the target folder
+    Extracted {
+        folder: String,
+        files: usize,
+        /// whether the archive was deleted afterwards
+        removed: bool,
+    },
+    /// the file looks like an archive of a format that cannot be unpacked
+    /// and was left as it is
+    Unsupported,
+    /// unpacking failed; the download itself still succeeded
+    Failed {
+        error: String,
+    },
+}
+
+
+/// Whether a tar header starts at the beginning of `header`
+fn is_tar_header(header: &[u8]) -> bool
+{
+    return header.len() >= 262 && &header[257..262] == b"ustar";
+}
+
+/// The first bytes of `reader`, up to a tar header
+fn read_header(reader: &mut dyn Read) -> Vec<u8>
+{
+    let mut header: Vec<u8> = Vec::with_capacity(512);
+    let _ = reader.take(512).read_to_end(&mut header);
+    return header;
+}
+
+/// Format of the archive at `path`, by its extension or else by its magic
+/// bytes; `Ok(None)` for files that are no archive at all
+pub(crate) fn detect_format(path: &Path) -> Result<Option<ArchiveFormat>, ZenodoDlError>
+{
+    let name: String = path.file_name().map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let by_extension: Option<ArchiveFormat> = if name.ends_with(".zip") {
+        Some(ArchiveFormat::Zip)
+    } else if name.ends_with(".tar") {
+        Some(ArchiveFormat::Tar)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(ArchiveFormat::TarGz)
+    } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+        Some(ArchiveFormat::TarZst)
+    } else {
+        None
+    };
+    if by_extension.is_some() {
+        return Ok(by_extension);
+    }
+    let mut magic: [u8; 4] = [0u8; 4];
+    let mut file = fs::File::open(path).map_err(io_error(path))?;
+    if file.read_exact(&mut magic).is_err() {
+        return Ok(None);
+    }
+    let reopen = || fs::File::open(path).map_err(io_error(path));
+    return Ok(match magic {
+        [0x50, 0x4b, 0x03, 0x04] => Some(ArchiveFormat::Zip),
+        // compressed files are only archives if they hold a tar
+        [0x1f, 0x8b, _, _] => is_tar_header(&read_header(&mut flate2::read::GzDecoder::new(reopen()?)))
+            .then_some(ArchiveFormat::TarGz),
+        [0x28, 0xb5, 0x2f, 0xfd] => match zstd::stream::read::Decoder::new(reopen()?) {
+            Ok(mut decoder) => is_tar_header(&read_header(&mut decoder)).then_some(ArchiveFormat::TarZst),
+            Err(_) => None
+        },
+        _ => is_tar_header(&read_header(&mut reopen()?)).then_some(ArchiveFormat::Tar)
+    });
+}
+
+/// Whether `path` looks like an archive or compressed file of a format
+/// `detect_format` does not know
+pub(crate) fn unsupported_archive(path: &Path) -> bool
+{
+    let name: String = path.file_name().map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    return UNSUPPORTED_EXTENSIONS.iter().any(|extension| name.ends_with(extension));
+}
+
+/// Folder below the target folder an archive is unpacked into with
+/// `DownloadOptions::extract_subfolders`: its local name without the
+/// archive extension, e.g. `data/set` for `data/set.tar.gz`
+pub(crate) fn archive_folder(local_name: &str) -> String
+{
+    let lowercase: String = local_name.to_lowercase();
+    for extension in [".tar.gz", ".tar.zst", ".tgz", ".tzst", ".tar", ".zip"] {
+        if lowercase.ends_with(extension) && lowercase.len() > extension.len() {
+            return local_name[..local_name.len() - extension.len()].to_string();
+        }
+    }
+    return format!("{}.extracted", local_name);
+}
+
+
+/// Where `name` of `archive` is unpacked to below `folder`, refusing names
+/// that would end up outside of it
+fn entry_path(archive: &Path, folder: &Path, name: &str) -> Result<PathBuf, ZenodoDlError>
+{
+    let unsafe_path = |reason: &str| ZenodoDlError::UnsafePath {
+        filename: format!("{} in {}", name, archive.display()),
+        reason: reason.to_string(),
+    };
+    if let Some(reason) = unsafe_key_reason(name) {
+        return Err(unsafe_path(reason));
+    }
+    let path: PathBuf = folder.join(name.trim_end_matches('/'));
+    if !is_inside(folder, &path) {
+        return Err(unsafe_path("outside the extraction folder"));
+    }
+    return Ok(path);
+}
+
+fn write_entry(path: &Path, content: &mut dyn Read) -> Result<(), ZenodoDlError>
+{
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(io_error(parent))?;
+    }
+    let mut file = fs::File::create(path).map_err(io_error(path))?;
+    io::copy(content, &mut file).map_err(io_error(path))?;
+    return Ok(());
+}
+
+fn invalid_archive(archive: &Path, reason: impl ToString) -> ZenodoDlError
+{
+    return ZenodoDlError::InvalidArchive { path: archive.to_path_buf(), reason: reason.to_string() };
+}
+
+fn extract_zip(archive: &Path, folder: &Path) -> Result<usize, ZenodoDlError>
+{
+    let file = fs::File::open(archive).map_err(io_error(archive))?;
+    let mut zip = zip::ZipArchive::new(BufReader::new(file)).map_err(|err| invalid_archive(archive, err))?;
+    // every name is checked before anything is written
+    for name in zip.file_names() {
+        entry_path(archive, folder, &name.map_err(|err| invalid_archive(archive, err))?)?;
+    }
+    let mut files: usize = 0;
+    for index in 0..zip.len() {
+        let mut entry = zip.by_index(index).map_err(|err| invalid_archive(archive, err))?;
+        let name: String = entry.name().map_err(|err| invalid_archive(archive, err))?.to_string();
+        let path: PathBuf = entry_path(archive, folder, &name)?;
+        if entry.is_dir() {
+            fs::create_dir_all(&path).map_err(io_error(&path))?;
+        } else if entry.is_file() {
+            write_entry(&path, &mut entry)?;
+            files += 1;
+        } else {
+            // links could point anywhere
+            tracing::debug!("skipping {} in {}: not a regular file", name, archive.display());
+        }
+    }
+    return Ok(files);
+}
+
+/// Reader of the tar stream of `archive`
+fn tar_reader(archive: &Path, format: ArchiveFormat) -> Result<Box<dyn Read>, ZenodoDlError>
+{
+    let file = BufReader::new(fs::File::open(archive).map_err(io_error(archive))?);
+    return Ok(match format {
+        ArchiveFormat::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+        ArchiveFormat::TarZst => Box::new(zstd::stream::read::Decoder::with_buffer(file)
+            .map_err(io_error(archive))?),
+        _ => Box::new(file)
+    });
+}
+
+fn extract_tar(archive: &Path, format: ArchiveFormat, folder: &Path) -> Result<usize, ZenodoDlError>
+{
+    // every name is checked before anything is written, which takes a
+    // first pass over the stream
+    let mut tar = tar::Archive::new(tar_reader(archive, format)?);
+    for entry in tar.entries().map_err(|err| invalid_archive(archive, err))? {
+        let entry = entry.map_err(|err| invalid_archive(archive, err))?;
+        let name: String = entry.path().map_err(|err| invalid_archive(archive, err))?
+            .to_string_lossy().to_string();
+        entry_path(archive, folder, &name)?;
+    }
+    let mut tar = tar::Archive::new(tar_reader(archive, format)?);
+    let mut files: usize = 0;
+    for entry in tar.entries().map_err(|err| invalid_archive(archive, err))? {
+        let mut entry = entry.map_err(|err| invalid_archive(archive, err))?;
+        let name: String = entry.path().map_err(|err| invalid_archive(archive, err))?
+            .to_string_lossy().to_string();
+        let path: PathBuf = entry_path(archive, folder, &name)?;
+        let entry_type: tar::EntryType = entry.header().entry_type();
+        if entry_type.is_dir() {
+            fs::create_dir_all(&path).map_err(io_error(&path))?;
+        } else if entry_type.is_file() {
+            write_entry(&path, &mut entry)?;
+            files += 1;
+        } else {
+            // links could point anywhere
+            tracing::debug!("skipping {} in {}: not a regular file", name, archive.display());
+        }
+    }
+    return Ok(files);
+}
+
+/// Unpacks `archive` into `folder`, returning the number of files
+/// written. Entries leading outside of `folder` fail the archive before
+/// anything is written, links are skipped.
+pub(crate) fn extract_archive(archive: &Path, format: ArchiveFormat, folder: &Path)
+    -> Result<usize, ZenodoDlError>
+{
+    fs::create_dir_all(folder).map_err(io_error(folder))?;
+    return match format {
+        ArchiveFormat::Zip => extract_zip(archive, folder),
+        _ => extract_tar(archive, format, folder)
+    };
+}
+
+/// Unpacks the downloaded file `local_name` in `target_folder` as the
+/// options ask; failures are reported, not returned, as the file itself
+/// was downloaded fine. `None` for files that are no archive.
+pub(crate) fn extract_download(target_folder: &str, local_name: &str, options: &DownloadOptions)
+    -> Option<Extraction>
+{
+    let archive: PathBuf = Path::new(target_folder).join(local_name);
+    let format: ArchiveFormat = match detect_format(&archive) {
+        Ok(Some(format)) => format,
+        Ok(None) if unsupported_archive(&archive) => {
+            warning!(options, "{} is compressed in a format that cannot be unpacked - leaving it in place",
+                local_name);
+            return Some(Extraction::Unsupported);
+        },
+        Ok(None) => return None,
+        Err(err) => return Some(Extraction::Failed { error: err.to_string() })
+    };
+    let folder: String = if options.extract_subfolders {
+        archive_folder(local_name)
+    } else {
+        // next to the archive, for archives in a folder of the record
+        Path::new(local_name).parent().map(|parent| parent.to_string_lossy().to_string())
+            .unwrap_or_default()
+    };
+    let files: usize = match extract_archive(&archive, format, &Path::new(target_folder).join(&folder)) {
+        Ok(files) => files,
+        Err(err) => {
+            warning!(options, "failed to unpack {}: {}", local_name, err);
+            return Some(Extraction::Failed { error: err.to_string() });
+        }
+    };
+    status!(options, "unpacked {} files from {}", files, local_name);
+    let removed: bool = options.remove_archives && match fs::remove_file(&archive) {
+        Ok(()) => true,
+        Err(err) => {
+            warning!(options, "failed to remove {} after unpacking it: {}", local_name, err);
+            false
+        }
+    };
+    let folder: String = if folder.is_empty() { ".".to_string() } else { folder };
+    return Some(Extraction::Extracted { folder, files, removed });
+}
@@ -0,0 +1,153 @@
+//! Selection of the files of a record that should be downloaded.
+
+use glob::Pattern;
+
+use crate::error::ZenodoDlError;
+use crate::FileInfo;
+
+
+/// Include/exclude glob patterns matched against file keys plus MIME
+/// types. A file is selected if it matches any include pattern (or there
+/// are none), none of the exclude patterns, and any of the MIME types (or
+/// there are none). Names added with `name` further restrict the
+/// selection to exactly these keys.
+#[derive(Clone, Debug, Default)]
+pub struct FileFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    mime_types: Vec<String>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    names: Vec<String>,
+}
+
+/// Parses sizes such as `500`, `500M`, `2GiB` or `1.5T`. Units without `i`
+/// are decimal (1k = 1000 bytes), units with `i` binary (1Ki = 1024 bytes),
+/// a trailing `B` is optional.
+pub fn parse_size(raw: &str) -> Result<u64, ZenodoDlError>
+{
+    let invalid = || ZenodoDlError::InvalidSize(raw.to_string());
+    let trimmed: &str = raw.trim();
+    let split_at: usize = trimmed.find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split_at);
+    let value: f64 = number.parse().map_err(|_| invalid())?;
+
+    let unit: String = unit.trim().to_ascii_lowercase();
+    let unit: &str = unit.strip_suffix('b').unwrap_or(&unit);
+    let (prefix, base): (&str, f64) = match unit.strip_suffix('i') {
+        Some(prefix) if !prefix.is_empty() => (prefix, 1024.0),
+        _ => (unit, 1000.0)
+    };
+    let exponent: i32 = match prefix {
+        "" => 0,
+        "k" => 1,
+        "m" => 2,
+        "g" => 3,
+        "t" => 4,
+        "p" => 5,
+        _ => return Err(invalid())
+    };
+    let bytes: f64 = (value * base.powi(exponent)).round();
+    // casting would quietly saturate sizes beyond 16 EiB
+    if bytes >= u64::MAX as f64 {
+        return Err(invalid());
+    }
+    return Ok(bytes as u64);
+}
+
+fn compile(pattern: &str) -> Result<Pattern, ZenodoDlError>
+{
+    return Pattern::new(pattern).map_err(|err| ZenodoDlError::InvalidPattern {
+        pattern: pattern.to_string(),
+        reason: err.msg.to_string(),
+    });
+}
+
+impl FileFilter {
+    pub fn new() -> Self
+    {
+        return FileFilter::default();
+    }
+
+    /// Adds a glob such as `*.csv` that files must match
+    pub fn include(mut self, pattern: &str) -> Result<Self, ZenodoDlError>
+    {
+        self.include.push(compile(pattern)?);
+        return Ok(self);
+    }
+
+    /// Adds a glob such as `raw/*` that excludes matching files
+    pub fn exclude(mut self, pattern: &str) -> Result<Self, ZenodoDlError>
+    {
+        self.exclude.push(compile(pattern)?);
+        return Ok(self);
+    }
+
+    /// Adds a MIME type files may have. Prefixes such as `image/` match
+    /// all subtypes, `*` matches everything. Files without a MIME type only
+    /// match `*` and the empty string.
+    pub fn mime_type(mut self, mime_type: &str) -> Self
+    {
+        self.mime_types.push(mime_type.trim().to_ascii_lowercase());
+        return self;
+    }
+
+    /// Restricts the selection to files with exactly this key
+    pub fn name(mut self, name: &str) -> Self
+    {
+        self.names.push(name.to_string());
+        return self;
+    }
+
+    /// Skips files smaller than `bytes`
+    pub fn min_size(mut self, bytes: u64) -> Self
+    {
+        self.min_size = Some(bytes);
+        return self;
+    }
+
+    /// Skips files larger than `bytes`
+    pub fn max_size(mut self, bytes: u64) -> Self
+    {
+        self.max_size = Some(bytes);
+        return self;
+    }
+
+    /// Whether no pattern was added, i.e. every file is selected
+    pub fn is_empty(&self) -> bool
+    {
+        return self.include.is_empty() && self.exclude.is_empty()
+            && self.mime_types.is_empty()
+            && self.min_size.is_none() && self.max_size.is_none()
+            && self.names.is_empty();
+    }
+
+    /// Whether a file of the given size passes the size limits
+    pub fn matches_size(&self, size: u64) -> bool
+    {
+        return self.min_size.is_none_or(|min_size| size >= min_size)
+            && self.max_size.is_none_or(|max_size| size <= max_size);
+    }
+
+    fn matches_mime_type(&self, mimetype: &str) -> bool
+    {
+        let mimetype: String = mimetype.trim().to_ascii_lowercase();
+        return self.mime_types.is_empty() || self.mime_types.iter().any(|wanted| {
+            wanted == "*" || (wanted.is_empty() && mimetype.is_empty())
+                || (!wanted.is_empty() && !mimetype.is_empty() && mimetype.starts_with(wanted.as_str()))
+        });
+    }
+
+    pub fn matches(&self, file: &FileInfo) -> bool
+    {
+        let filename: &str = &file.filename;
+        let included: bool = self.include.is_empty()
+            || self.include.iter().any(|pattern| pattern.matches(filename));
+        let named: bool = self.names.is_empty()
+            || self.names.iter().any(|name| name == filename);
+        return included && named && !self.exclude.iter().any(|pattern| pattern.matches(filename))
+            && self.matches_mime_type(&file.mimetype)
+            && self.matches_size(file.size);
+    }
+}
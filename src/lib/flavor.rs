@@ -0,0 +1,108 @@
+//! Differences between the API of Zenodo and those of other InvenioRDM
+//! instances such as CaltechDATA or TU Wien's research data repository.
+
+use reqwest::Url;
+
+
+const ZENODO_HOSTS: [&str; 2] = [
+    "zenodo.org",
+    "sandbox.zenodo.org",
+];
+
+
+/// Dialect of the records API spoken by the server at the base URL
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApiFlavor {
+    /// zenodo.org, which still serves parts of its legacy API next to the
+    /// InvenioRDM one
+    Zenodo,
+    /// any other InvenioRDM instance
+    InvenioRdm,
+}
+
+impl ApiFlavor {
+    /// Zenodo for the Zenodo hosts, InvenioRDM for any other base URL
+    pub fn detect(base_url: &str) -> ApiFlavor
+    {
+        let host: Option<String> = Url::parse(base_url).ok()
+            .and_then(|url| url.host_str().map(|host| host.to_lowercase()));
+        return match host.as_deref().map(|host| host.strip_prefix("www.").unwrap_or(host)) {
+            Some(host) if ZENODO_HOSTS.contains(&host) => ApiFlavor::Zenodo,
+            _ => ApiFlavor::InvenioRdm
+        };
+    }
+
+    pub fn name(&self) -> &'static str
+    {
+        return match self {
+            ApiFlavor::Zenodo => "Zenodo",
+            ApiFlavor::InvenioRdm => "InvenioRDM",
+        };
+    }
+
+    /// Search query finding the record with `doi`; InvenioRDM has no
+    /// `doi` shorthand field
+    pub(crate) fn doi_query(&self, doi: &str) -> String
+    {
+        return match self {
+            ApiFlavor::Zenodo => format!("doi:\"{}\"", doi),
+            ApiFlavor::InvenioRdm => format!("pids.doi.identifier:\"{}\"", doi),
+        };
+    }
+
+    /// Path and query parameters of the search listing the records of a
+    /// community
+    pub(crate) fn community_search<'a>(&self, community: &'a str)
+        -> (String, Vec<(&'static str, &'a str)>)
+    {
+        return match self {
+            ApiFlavor::Zenodo => ("/api/records".to_string(), vec![("communities", community)]),
+            ApiFlavor::InvenioRdm => (format!("/api/communities/{}/records", community), Vec::new()),
+        };
+    }
+
+    /// URL the content of a file is downloaded from. Zenodo's legacy
+    /// entries only have `links.self`, which is the content itself; on
+    /// InvenioRDM it is the file metadata, with the content below it.
+    pub(crate) fn content_url(&self, content: Option<&str>, links_self: Option<&str>) -> Option<String>
+    {
+        if let Some(content) = content.filter(|content| !content.is_empty()) {
+            return Some(content.to_string());
+        }
+        let links_self: &str = links_self.filter(|links_self| !links_self.is_empty())?;
+        return match self {
+            ApiFlavor::Zenodo => Some(links_self.to_string()),
+            ApiFlavor::InvenioRdm => Some(links_self.trim_end_matches('/').to_string() + "/content"),
+        };
+    }
+
+    /// Whether records may list their files in the legacy record
+    /// representation only, see `fetch_legacy_files`
+    pub(crate) fn has_legacy_files(&self) -> bool
+    {
+        return *self == ApiFlavor::Zenodo;
+    }
+}
+
+
+/// Content URL of the file `key` of the record (or draft) at `record_url`,
+/// as both APIs serve it, for entries that come without links
+pub(crate) fn file_content_url(record_url: &str, key: &str) -> String
+{
+    return format!("{}/files/{}/content", record_url.trim_end_matches('/'), encode_key(key));
+}
+
+/// `key` percent-encoded for a URL path, keeping the `/` of keys in
+/// folders; `+` is encoded as well, as some servers read it as a space
+fn encode_key(key: &str) -> String
+{
+    let mut encoded: String = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~' | b'/') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    return encoded;
+}
@@ -0,0 +1,148 @@
+//! Decompressing `.gz` files while they are downloaded, see
+//! `DownloadOptions::decompress`. The checksum of the record covers the
+//! compressed bytes, so those are hashed before they reach the decoder.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use flate2::write::MultiGzDecoder;
+
+use crate::checksum::{Checksum, ChecksumAlgorithm};
+use crate::manifest;
+use crate::FileData;
+
+
+/// Local name of the file `name` decompressed, `None` if it is no gzip
+/// file or a tar archive, which `DownloadOptions::extract` unpacks instead
+pub(crate) fn gunzipped_name(name: &str) -> Option<String>
+{
+    let lowercase: String = name.to_lowercase();
+    if !lowercase.ends_with(".gz") || lowercase.ends_with(".tar.gz") || name.len() <= 3
+        || name.ends_with("/.gz") {
+        return None;
+    }
+    return Some(name[..name.len() - 3].to_string());
+}
+
+/// Whether `entry` is stored decompressed, i.e. its local name lost the
+/// `.gz` of its key
+pub(crate) fn is_gunzipped(entry: &FileData) -> bool
+{
+    return gunzipped_name(&entry.filename).is_some() && !entry.local_name.to_lowercase().ends_with(".gz");
+}
+
+/// Checksum of the decompressed copy of `entry` at `filepath` as listed
+/// in the manifest when it was downloaded; the one of the record only
+/// fits the compressed file
+pub(crate) fn local_checksum(filepath: &Path, entry: &FileData) -> Option<Checksum>
+{
+    let algorithm: ChecksumAlgorithm = entry.checksum.as_ref()?.known_algorithm()?;
+    // the manifests are in the target folder, above the folders of the key
+    let folder: &Path = filepath.ancestors().nth(Path::new(&entry.local_name).components().count())?;
+    let digest: String = manifest::lookup(folder, algorithm, &entry.local_name)?;
+    return Checksum::parse(&format!("{}:{}", algorithm.name(), digest));
+}
+
+
+/// Decompresses the gzip data written to it into `inner`. Data that is no
+/// valid gzip does not fail the writes, so that the download is still
+/// checked against its checksum first, see `GunzipWriter::finish`.
+pub(crate) struct GunzipWriter<W: Write> {
+    decoder: MultiGzDecoder<W>,
+    /// why decompressing failed, nothing is written after that
+    invalid: Option<io::Error>,
+}
+
+impl<W: Write> GunzipWriter<W> {
+    pub(crate) fn new(inner: W) -> GunzipWriter<W>
+    {
+        return GunzipWriter { decoder: MultiGzDecoder::new(inner), invalid: None };
+    }
+
+    /// Decompresses `data`, failing only if `inner` does
+    pub(crate) fn write_compressed(&mut self, data: &[u8]) -> io::Result<()>
+    {
+        if self.invalid.is_some() {
+            return Ok(());
+        }
+        return match self.decoder.write_all(data) {
+            Err(err) if matches!(err.kind(), io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput) => {
+                self.invalid = Some(err);
+                Ok(())
+            },
+            other => other
+        };
+    }
+
+    /// Flushes what was decompressed so far into `inner`
+    pub(crate) fn flush(&mut self) -> io::Result<()>
+    {
+        if self.invalid.is_some() {
+            return Ok(());
+        }
+        return self.decoder.flush();
+    }
+
+    /// Ends the gzip stream, returning `inner` with all data written, or
+    /// the reason the data could not be decompressed as the inner `Err`
+    pub(crate) fn finish(mut self) -> io::Result<Result<W, String>>
+    {
+        if let Some(err) = self.invalid.take() {
+            return Ok(Err(err.to_string()));
+        }
+        return match self.decoder.try_finish() {
+            Ok(()) => self.decoder.finish().map(Ok),
+            Err(err) if matches!(err.kind(), io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput
+                | io::ErrorKind::UnexpectedEof | io::ErrorKind::WriteZero) => Ok(Err(err.to_string())),
+            Err(err) => Err(err)
+        };
+    }
+}
+
+
+/// File a download is written to, decompressing it on the way if asked
+pub(crate) enum OutputFile<W: Write> {
+    Plain(W),
+    Gunzip(Box<GunzipWriter<W>>),
+}
+
+impl<W: Write> OutputFile<W> {
+    pub(crate) fn new(inner: W, gunzip: bool) -> OutputFile<W>
+    {
+        return match gunzip {
+            true => OutputFile::Gunzip(Box::new(GunzipWriter::new(inner))),
+            false => OutputFile::Plain(inner)
+        };
+    }
+
+    /// Writes `data` as received from the server
+    pub(crate) fn write_all(&mut self, data: &[u8]) -> io::Result<()>
+    {
+        return match self {
+            OutputFile::Plain(inner) => inner.write_all(data),
+            OutputFile::Gunzip(writer) => writer.write_compressed(data)
+        };
+    }
+
+    pub(crate) fn flush(&mut self) -> io::Result<()>
+    {
+        return match self {
+            OutputFile::Plain(inner) => inner.flush(),
+            OutputFile::Gunzip(writer) => writer.flush()
+        };
+    }
+
+    /// Writes out everything and closes the file; the inner `Err` tells
+    /// why the data could not be decompressed
+    pub(crate) fn finish(self) -> io::Result<Result<(), String>>
+    {
+        let mut inner: W = match self {
+            OutputFile::Plain(inner) => inner,
+            OutputFile::Gunzip(writer) => match writer.finish()? {
+                Ok(inner) => inner,
+                Err(reason) => return Ok(Err(reason))
+            }
+        };
+        return inner.flush().map(Ok);
+    }
+}
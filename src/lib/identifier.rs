@@ -0,0 +1,140 @@
+//! Parsing of the record identifiers users paste into the CLI.
+
+use reqwest::Url;
+
+use crate::error::ZenodoDlError;
+
+const ZENODO_DOI_PREFIX: &str = "10.5281/zenodo.";
+const DOI_PREFIXES: [&str; 5] = [
+    "https://doi.org/",
+    "http://doi.org/",
+    "https://dx.doi.org/",
+    "http://dx.doi.org/",
+    "doi:",
+];
+const KNOWN_HOSTS: [&str; 2] = [
+    "zenodo.org",
+    "sandbox.zenodo.org",
+];
+
+
+/// A record identifier as given by the user
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecordIdentifier {
+    /// record id, usable as is: numeric on Zenodo, like `a1b2c-3d4e5` on
+    /// other InvenioRDM instances
+    RecordId(String),
+    /// DOI that has to be resolved to a record id first
+    Doi(String),
+}
+
+impl RecordIdentifier {
+    /// The record id or the bare DOI, e.g. to pass on as identifier
+    pub fn as_str(&self) -> &str
+    {
+        return match self {
+            RecordIdentifier::RecordId(record_id) => record_id,
+            RecordIdentifier::Doi(doi) => doi
+        };
+    }
+}
+
+
+fn is_record_id(candidate: &str) -> bool
+{
+    if !candidate.is_empty() && candidate.chars().all(|c| c.is_ascii_digit()) {
+        return true;
+    }
+    // InvenioRDM ids are two groups of five lowercase letters and digits
+    let groups: Vec<&str> = candidate.split('-').collect();
+    return groups.len() == 2 && groups.iter().all(|group| group.len() == 5
+        && group.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()));
+}
+
+
+fn strip_prefix_ignore_case<'a>(value: &'a str, prefix: &str) -> Option<&'a str>
+{
+    if value.len() >= prefix.len()
+        && value.is_char_boundary(prefix.len())
+        && value[..prefix.len()].eq_ignore_ascii_case(prefix) {
+        return Some(&value[prefix.len()..]);
+    }
+    return None;
+}
+
+
+fn normalized_host(url: &Url) -> Option<String>
+{
+    let host: &str = url.host_str()?;
+    return Some(host.strip_prefix("www.").unwrap_or(host).to_lowercase());
+}
+
+
+/// Extracts the record id from a record page URL such as
+/// `https://zenodo.org/records/1234567#files`, the older
+/// `/record/1234567` form or the `/uploads/1234567` page of a draft. Only
+/// the Zenodo hosts and the host of `base_url` are accepted.
+fn parse_record_url(raw: &str, base_url: &str) -> Result<String, String>
+{
+    let url: Url = Url::parse(raw).or(Err(format!("'{}' is not a valid URL", raw)))?;
+    let host: String = normalized_host(&url).ok_or(format!("'{}' has no host", raw))?;
+    let base_host: Option<String> = Url::parse(base_url).ok()
+        .and_then(|base| normalized_host(&base));
+    if !KNOWN_HOSTS.contains(&host.as_str()) && base_host.as_deref() != Some(host.as_str()) {
+        return Err(format!("'{}' points to {}, not to a Zenodo record - use --base-url for other instances",
+            raw, host));
+    }
+
+    let segments: Vec<&str> = url.path_segments()
+        .map(|segments| segments.filter(|segment| !segment.is_empty()).collect())
+        .unwrap_or_default();
+    for window in segments.windows(2) {
+        if matches!(window[0], "records" | "record" | "uploads") && is_record_id(window[1]) {
+            return Ok(window[1].to_string());
+        }
+    }
+    return Err(format!("'{}' does not point to a record, expected a URL like https://{}/records/1234567",
+        raw, host));
+}
+
+
+/// Parses a record id, a record page URL, or a DOI with or without a
+/// resolver prefix such as `https://doi.org/` or `doi:`. Zenodo DOIs
+/// (`10.5281/zenodo.<id>`) are turned into record ids right away.
+/// `base_url` is the instance the record is downloaded from; record URLs
+/// pointing at other hosts are rejected.
+pub fn parse_identifier(raw: &str, base_url: &str) -> Result<RecordIdentifier, ZenodoDlError>
+{
+    let trimmed: &str = raw.trim();
+    if is_record_id(trimmed) {
+        return Ok(RecordIdentifier::RecordId(trimmed.to_string()));
+    }
+
+    let mut doi: &str = trimmed;
+    for prefix in DOI_PREFIXES.iter() {
+        if let Some(stripped) = strip_prefix_ignore_case(doi, prefix) {
+            doi = stripped;
+            break;
+        }
+    }
+
+    if doi == trimmed && (strip_prefix_ignore_case(trimmed, "https://").is_some()
+        || strip_prefix_ignore_case(trimmed, "http://").is_some()) {
+        return parse_record_url(trimmed, base_url)
+            .map(RecordIdentifier::RecordId)
+            .map_err(ZenodoDlError::InvalidIdentifier);
+    }
+
+    if let Some(record_id) = strip_prefix_ignore_case(doi, ZENODO_DOI_PREFIX) {
+        if is_record_id(record_id) {
+            return Ok(RecordIdentifier::RecordId(record_id.to_string()));
+        }
+    }
+
+    if doi.starts_with("10.") && doi.contains('/') {
+        return Ok(RecordIdentifier::Doi(doi.to_string()));
+    }
+
+    return Err(ZenodoDlError::InvalidIdentifier(
+        format!("'{}' is neither a Zenodo record id, record URL, nor a DOI", raw)));
+}
@@ -0,0 +1,166 @@
+//! Progress as JSON lines, one object per event, for programs driving the
+//! downloads such as GUI wrappers or CI dashboards.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Serialize, Deserialize};
+
+use crate::error::ZenodoDlError;
+use crate::progress::ProgressObserver;
+
+
+/// Shortest time between two `progress` events of the same file
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+
+/// A line written by `JsonLinesProgress`, tagged by its `event` field, e.g.
+/// `{"event":"progress","name":"data.csv","bytes":1024,"total":4096}`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    /// the download of `files` files starts, e.g. those of one record
+    DownloadStart {
+        files: usize,
+    },
+    /// an attempt to download a file starts, again for each retry; `size`
+    /// is 0 if the record does not tell
+    FileStart {
+        name: String,
+        size: u64,
+    },
+    /// `bytes` of the file of `total` bytes are present so far, sent a few
+    /// times per second at most; `total` is 0 while the size is unknown
+    Progress {
+        name: String,
+        bytes: u64,
+        total: u64,
+    },
+    /// the file was downloaded and verified, or existed already
+    FileDone {
+        name: String,
+    },
+    /// the file failed for good, after all retries
+    FileFailed {
+        name: String,
+        reason: String,
+    },
+    /// the run is over
+    DownloadDone,
+}
+
+
+/// Size of a file in flight and when its last `progress` event was sent
+struct FileState {
+    size: u64,
+    last_event: Option<Instant>,
+}
+
+
+/// Writes each `ProgressEvent` as a line of JSON, to stderr by default
+pub struct JsonLinesProgress {
+    writer: Mutex<Box<dyn Write + Send>>,
+    files: Mutex<HashMap<String, FileState>>,
+}
+
+impl JsonLinesProgress {
+    pub fn new() -> JsonLinesProgress
+    {
+        return JsonLinesProgress::with_writer(Box::new(io::stderr()));
+    }
+
+    /// Writes the events to `writer` instead of stderr
+    pub fn with_writer(writer: Box<dyn Write + Send>) -> JsonLinesProgress
+    {
+        return JsonLinesProgress {
+            writer: Mutex::new(writer),
+            files: Mutex::new(HashMap::new()),
+        };
+    }
+
+    /// Writes `event` as a single line; a consumer gone away does not stop
+    /// the download
+    fn emit(&self, event: &ProgressEvent)
+    {
+        let Ok(mut line) = serde_json::to_string(event) else {
+            return;
+        };
+        line.push('\n');
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = writer.write_all(line.as_bytes()).and_then(|_| writer.flush());
+        }
+    }
+}
+
+impl Default for JsonLinesProgress {
+    fn default() -> Self
+    {
+        return JsonLinesProgress::new();
+    }
+}
+
+impl ProgressObserver for JsonLinesProgress {
+    fn download_started(&self, files: usize)
+    {
+        self.emit(&ProgressEvent::DownloadStart { files });
+    }
+
+    fn file_started(&self, filename: &str, size: u64)
+    {
+        if let Ok(mut files) = self.files.lock() {
+            files.insert(filename.to_string(), FileState { size, last_event: None });
+        }
+        self.emit(&ProgressEvent::FileStart { name: filename.to_string(), size });
+    }
+
+    fn file_size_known(&self, filename: &str, size: u64)
+    {
+        if let Ok(mut files) = self.files.lock() {
+            if let Some(file) = files.get_mut(filename) {
+                file.size = size;
+            }
+        }
+    }
+
+    fn bytes_advanced(&self, filename: &str, bytes: u64)
+    {
+        let total: u64 = {
+            let Ok(mut files) = self.files.lock() else {
+                return;
+            };
+            let Some(file) = files.get_mut(filename) else {
+                return;
+            };
+            let due: bool = file.last_event.is_none_or(|last| last.elapsed() >= PROGRESS_INTERVAL);
+            if !due && (bytes < file.size || file.size == 0) {
+                return;
+            }
+            file.last_event = Some(Instant::now());
+            file.size
+        };
+        self.emit(&ProgressEvent::Progress { name: filename.to_string(), bytes, total });
+    }
+
+    fn file_finished(&self, filename: &str)
+    {
+        if let Ok(mut files) = self.files.lock() {
+            files.remove(filename);
+        }
+        self.emit(&ProgressEvent::FileDone { name: filename.to_string() });
+    }
+
+    fn file_failed(&self, filename: &str, error: &ZenodoDlError)
+    {
+        if let Ok(mut files) = self.files.lock() {
+            files.remove(filename);
+        }
+        self.emit(&ProgressEvent::FileFailed { name: filename.to_string(), reason: error.to_string() });
+    }
+
+    fn download_finished(&self)
+    {
+        self.emit(&ProgressEvent::DownloadDone);
+    }
+}
@@ -0,0 +1,166 @@
+//! Folder names of records downloaded into a shared output folder.
+
+use crate::error::ZenodoDlError;
+use crate::paths::WINDOWS_RESERVED;
+use crate::record::RecordMetadata;
+
+
+/// Longest expansion of a title placeholder, titles can be whole sentences
+const MAX_TITLE_LENGTH: usize = 80;
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Field {
+    RecordId,
+    Title,
+    TitleSlug,
+    Version,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Field(Field),
+}
+
+
+/// Name of the folder each record is placed in, e.g.
+/// `{record_id}-{title_slug}`. Supported placeholders are `{record_id}`,
+/// `{title}`, `{title_slug}` and `{version}`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubdirTemplate {
+    template: String,
+    segments: Vec<Segment>,
+}
+
+impl SubdirTemplate {
+    /// Template placing each record in a folder named by its id
+    pub fn record_id() -> Self
+    {
+        return SubdirTemplate {
+            template: "{record_id}".to_string(),
+            segments: vec![Segment::Field(Field::RecordId)],
+        };
+    }
+
+    pub fn parse(template: &str) -> Result<Self, ZenodoDlError>
+    {
+        let invalid = |reason: &str| ZenodoDlError::InvalidTemplate {
+            template: template.to_string(),
+            reason: reason.to_string(),
+        };
+        let mut segments: Vec<Segment> = Vec::new();
+        let mut rest: &str = template;
+        while let Some(start) = rest.find('{') {
+            let end: usize = rest[start..].find('}').ok_or_else(|| invalid("unclosed '{'"))? + start;
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+            let field: Field = match &rest[start + 1..end] {
+                "record_id" => Field::RecordId,
+                "title" => Field::Title,
+                "title_slug" => Field::TitleSlug,
+                "version" => Field::Version,
+                other => return Err(invalid(&format!("unknown placeholder {{{}}}", other)))
+            };
+            segments.push(Segment::Field(field));
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+        for segment in segments.iter() {
+            if let Segment::Text(text) = segment {
+                if text.contains(['/', '\\', '}']) {
+                    return Err(invalid("must be a single folder name"));
+                }
+            }
+        }
+        if !segments.iter().any(|segment| matches!(segment, Segment::Field(_))) {
+            return Err(invalid("needs at least one placeholder, e.g. {record_id}"));
+        }
+        return Ok(SubdirTemplate { template: template.to_string(), segments });
+    }
+
+    /// Whether expanding needs the record metadata, not just the id
+    pub fn needs_metadata(&self) -> bool
+    {
+        return self.segments.iter().any(|segment| matches!(segment,
+            Segment::Field(Field::Title | Field::TitleSlug | Field::Version)));
+    }
+
+    /// Folder name of a record. Titles and versions are sanitized, so the
+    /// result is a single valid folder name on all platforms.
+    pub fn expand(&self, record_id: &str, metadata: Option<&RecordMetadata>) -> String
+    {
+        let title: &str = metadata.map(|metadata| metadata.title.as_str()).unwrap_or("");
+        let mut name: String = String::new();
+        for segment in self.segments.iter() {
+            match segment {
+                Segment::Text(text) => name.push_str(text),
+                Segment::Field(Field::RecordId) => name.push_str(record_id),
+                Segment::Field(Field::Title) =>
+                    name.push_str(&truncate(&sanitize(title), MAX_TITLE_LENGTH)),
+                Segment::Field(Field::TitleSlug) =>
+                    name.push_str(&truncate(&slug(title), MAX_TITLE_LENGTH)),
+                Segment::Field(Field::Version) => name.push_str(&sanitize(
+                    metadata.and_then(|metadata| metadata.version.as_deref()).unwrap_or("")))
+            };
+        }
+        return finish_name(&sanitize(&name), record_id);
+    }
+
+    pub fn as_str(&self) -> &str
+    {
+        return &self.template;
+    }
+}
+
+
+/// Replaces characters that are not allowed in file names on Windows or
+/// separate paths anywhere
+fn sanitize(text: &str) -> String
+{
+    return text.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c
+        })
+        .collect();
+}
+
+/// Lowercase ASCII letters and digits, every other run of characters
+/// turned into a single `-`
+fn slug(text: &str) -> String
+{
+    let mut slug: String = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    return slug.trim_end_matches('-').to_string();
+}
+
+fn truncate(text: &str, max_chars: usize) -> String
+{
+    return text.chars().take(max_chars).collect::<String>().trim_end().to_string();
+}
+
+/// Windows drops trailing dots and spaces and reserves device names; an
+/// empty name, `.` or `..` would not be a folder of its own
+fn finish_name(name: &str, record_id: &str) -> String
+{
+    let trimmed: &str = name.trim().trim_end_matches('.');
+    if trimmed.is_empty() {
+        return record_id.to_string();
+    }
+    let stem: String = trimmed.split('.').next().unwrap_or("").to_ascii_lowercase();
+    if WINDOWS_RESERVED.contains(&stem.as_str()) {
+        return format!("_{}", trimmed);
+    }
+    return trimmed.to_string();
+}
@@ -0,0 +1,155 @@
+//! Checksum manifests in the format of coreutils' `md5sum` and friends,
+//! e.g. `MD5SUMS` lines like `<hex digest>  <filename>`.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::checksum::{Checksum, ChecksumAlgorithm};
+use crate::error::ZenodoDlError;
+
+
+/// Name of the manifest of an algorithm, as used by release mirrors
+pub(crate) fn manifest_filename(algorithm: ChecksumAlgorithm) -> &'static str
+{
+    return match algorithm {
+        ChecksumAlgorithm::Md5 => "MD5SUMS",
+        ChecksumAlgorithm::Sha1 => "SHA1SUMS",
+        ChecksumAlgorithm::Sha256 => "SHA256SUMS",
+        ChecksumAlgorithm::Sha512 => "SHA512SUMS",
+    };
+}
+
+
+/// A line of a manifest; names with a backslash or newline are escaped
+/// and the line starts with `\`, like coreutils does
+fn format_line(digest: &str, filename: &str) -> String
+{
+    if filename.contains(['\\', '\n', '\r']) {
+        let escaped: String = filename.replace('\\', "\\\\").replace('\n', "\\n").replace('\r', "\\r");
+        return format!("\\{}  {}", digest, escaped);
+    }
+    return format!("{}  {}", digest, filename);
+}
+
+/// Splits a manifest line into digest and filename. Both the text mode
+/// (`hash  name`) and the binary mode (`hash *name`) format are accepted;
+/// a trailing `\r` of manifests written on Windows is ignored.
+pub(crate) fn parse_line(line: &str) -> Option<(String, String)>
+{
+    let line: &str = line.strip_suffix('\r').unwrap_or(line);
+    let (escaped, line): (bool, &str) = match line.strip_prefix('\\') {
+        Some(rest) => (true, rest),
+        None => (false, line)
+    };
+    let (digest, rest): (&str, &str) = line.split_once(' ')?;
+    let filename: &str = rest.strip_prefix(' ').or_else(|| rest.strip_prefix('*'))?;
+    if digest.is_empty() || filename.is_empty() {
+        return None;
+    }
+    let filename: String = if escaped {
+        let mut unescaped: String = String::with_capacity(filename.len());
+        let mut chars = filename.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                unescaped.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some('r') => unescaped.push('\r'),
+                Some(other) => unescaped.push(other),
+                None => unescaped.push('\\')
+            };
+        }
+        unescaped
+    } else {
+        filename.to_string()
+    };
+    return Some((digest.to_string(), filename));
+}
+
+
+/// Comment naming the file of the record `local_name` was decompressed
+/// from and its checksum, placed before the line of `local_name`
+fn format_source_comment(local_name: &str, filename: &str, digest: &str) -> String
+{
+    return format!("# {} decompressed from {}  {}", local_name.replace('\n', "\\n"),
+        filename.replace('\n', "\\n"), digest);
+}
+
+/// Whether `line` is the comment `format_source_comment` wrote for one
+/// of `local_names`
+fn is_source_comment_of(line: &str, local_names: &HashSet<&str>) -> bool
+{
+    return line.strip_prefix("# ")
+        .and_then(|comment| comment.split_once(" decompressed from "))
+        .is_some_and(|(local_name, _)| local_names.contains(local_name));
+}
+
+
+/// A file to list in the manifests
+pub(crate) struct ManifestEntry<'a> {
+    /// path of the file below the folder of the manifests
+    pub(crate) local_name: &'a str,
+    /// checksum of the local file
+    pub(crate) checksum: &'a Checksum,
+    /// key and checksum of the file of the record if the local file is its
+    /// decompressed content
+    pub(crate) compressed: Option<(&'a str, &'a Checksum)>,
+}
+
+
+/// Digest listed for `local_name` in the manifest of `algorithm` in
+/// `folder`, if any
+pub(crate) fn lookup(folder: &Path, algorithm: ChecksumAlgorithm, local_name: &str) -> Option<String>
+{
+    let content: String = fs::read_to_string(folder.join(manifest_filename(algorithm))).ok()?;
+    return content.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(parse_line)
+        .find(|(_, filename)| filename == local_name)
+        .map(|(digest, _)| digest);
+}
+
+/// Adds the checksums of `files` to the manifests in `folder`, one per
+/// algorithm. Lines of files that are not part of `files` are kept, so
+/// records downloaded into the same folder share the manifests; lines of
+/// the same file are replaced.
+pub(crate) fn update_manifests(folder: &Path, files: &[ManifestEntry]) -> Result<(), ZenodoDlError>
+{
+    for algorithm in [ChecksumAlgorithm::Md5, ChecksumAlgorithm::Sha1,
+        ChecksumAlgorithm::Sha256, ChecksumAlgorithm::Sha512] {
+        let entries: Vec<&ManifestEntry> = files.iter()
+            .filter(|entry| entry.checksum.known_algorithm() == Some(algorithm))
+            .collect();
+        if entries.is_empty() {
+            continue;
+        }
+        let path: PathBuf = folder.join(manifest_filename(algorithm));
+        let replaced: HashSet<&str> = entries.iter().map(|entry| entry.local_name).collect();
+
+        let mut lines: Vec<String> = match fs::read_to_string(&path) {
+            Ok(existing) => existing.lines()
+                .filter(|line| !is_source_comment_of(line, &replaced))
+                .filter(|line| match parse_line(line) {
+                    Some((_, filename)) if !line.starts_with('#') => !replaced.contains(filename.as_str()),
+                    _ => !line.trim().is_empty()
+                })
+                .map(|line| line.trim_end_matches('\r').to_string())
+                .collect(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(source) => return Err(ZenodoDlError::Io { path, source })
+        };
+        for entry in entries.iter() {
+            if let Some((filename, checksum)) = entry.compressed {
+                lines.push(format_source_comment(entry.local_name, filename, &checksum.value));
+            }
+            lines.push(format_line(&entry.checksum.value, entry.local_name));
+        }
+        lines.push(String::new());
+        fs::write(&path, lines.join("\n")).map_err(|source| ZenodoDlError::Io {
+            path: path.clone(), source })?;
+    }
+    return Ok(());
+}
@@ -0,0 +1,38 @@
+//! Order in which the files of a record are downloaded.
+
+use std::cmp::Reverse;
+
+use crate::FileData;
+
+
+/// Order in which the files of a record are downloaded, with concurrent
+/// downloads the order in which they are started. Reports and manifests
+/// follow the order of the record regardless.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum DownloadOrder {
+    /// as listed by the record
+    #[default]
+    AsListed,
+    /// small files first, e.g. to work with them while a large one still
+    /// trickles in
+    SmallestFirst,
+    LargestFirst,
+    /// by file key, ignoring case
+    Alphabetical,
+}
+
+impl DownloadOrder {
+    /// Indices of `files` in the order they are downloaded in; files that
+    /// compare equal keep the order of the record
+    pub(crate) fn arrange(&self, files: &[FileData]) -> Vec<usize>
+    {
+        let mut indices: Vec<usize> = (0..files.len()).collect();
+        match self {
+            DownloadOrder::AsListed => (),
+            DownloadOrder::SmallestFirst => indices.sort_by_key(|index| files[*index].size),
+            DownloadOrder::LargestFirst => indices.sort_by_key(|index| Reverse(files[*index].size)),
+            DownloadOrder::Alphabetical => indices.sort_by_key(|index| files[*index].filename.to_lowercase()),
+        };
+        return indices;
+    }
+}
@@ -0,0 +1,131 @@
+//! Local paths of file keys, which come from the server and must never
+//! point outside the target folder.
+
+use std::path::{Component, Path, PathBuf};
+
+
+/// Names Windows reserves for devices, with or without extension
+pub(crate) const WINDOWS_RESERVED: [&str; 22] = [
+    "con", "prn", "aux", "nul",
+    "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8", "com9",
+    "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Characters Windows does not allow in file names, besides separators
+/// and control characters
+const WINDOWS_INVALID: [char; 7] = [':', '*', '?', '"', '<', '>', '|'];
+
+
+/// What to do with file keys like `../../.bashrc` or `/etc/passwd`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum UnsafePathPolicy {
+    /// leave the file out with a warning
+    #[default]
+    Skip,
+    /// fail the whole record
+    Fail,
+}
+
+
+/// Why a key cannot be used as path below the target folder, `None` if it
+/// can. Both `/` and `\` count as separators, so keys are judged the same
+/// on every platform.
+pub(crate) fn unsafe_key_reason(key: &str) -> Option<&'static str>
+{
+    if key.trim().is_empty() {
+        return Some("empty file name");
+    }
+    if key.starts_with(['/', '\\']) {
+        return Some("absolute path");
+    }
+    let first: &str = key.split(['/', '\\']).next().unwrap_or("");
+    let bytes: &[u8] = first.as_bytes();
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        return Some("drive prefix");
+    }
+    if key.split(['/', '\\']).any(|component| component == "..") {
+        return Some("parent directory component");
+    }
+    if key.contains('\0') {
+        return Some("NUL character");
+    }
+    return None;
+}
+
+
+/// Checks that `filepath` stays inside `target_folder` once symlinks are
+/// resolved, e.g. when a subfolder of the target is a link elsewhere.
+/// Only the parts of the path that exist already are resolved.
+pub(crate) fn is_inside(target_folder: &Path, filepath: &Path) -> bool
+{
+    let root: PathBuf = match target_folder.canonicalize() {
+        Ok(root) => root,
+        Err(_) => return false
+    };
+    let mut existing: &Path = filepath;
+    let mut rest: Vec<Component> = Vec::new();
+    while !existing.exists() {
+        match (existing.parent(), existing.components().next_back()) {
+            (Some(parent), Some(last)) => { rest.push(last); existing = parent; },
+            _ => return false
+        };
+    }
+    let resolved: PathBuf = match existing.canonicalize() {
+        Ok(resolved) => resolved,
+        Err(_) => return false
+    };
+    if rest.iter().any(|component| !matches!(component, Component::Normal(_))) {
+        return false;
+    }
+    return resolved.starts_with(&root);
+}
+
+
+/// One component of a key made valid on Windows: invalid characters are
+/// replaced, trailing dots and spaces (which Windows drops) too, and device
+/// names like `CON` get the replacement prefixed
+fn portable_component(component: &str, replacement: char) -> String
+{
+    let mut name: String = component.chars()
+        .map(|c| if WINDOWS_INVALID.contains(&c) || c.is_control() { replacement } else { c })
+        .collect();
+    let kept: usize = name.trim_end_matches(['.', ' ']).len();
+    let trailing: usize = name[kept..].chars().count();
+    name.truncate(kept);
+    name.extend(std::iter::repeat_n(replacement, trailing));
+    let stem: String = name.split('.').next().unwrap_or("").trim_end().to_ascii_lowercase();
+    if WINDOWS_RESERVED.contains(&stem.as_str()) {
+        name.insert(0, replacement);
+    }
+    return name;
+}
+
+/// Relative path a key is stored at, with `/` as separator. Keys are kept
+/// as they are unless `portable` asks for names that are valid on Windows
+/// too; backslashes then separate folders as they would on Windows.
+/// `flatten` stores every file directly in the target folder, folders in
+/// the key joined to the name by `replacement`.
+pub(crate) fn local_name(key: &str, portable: bool, flatten: bool, replacement: char) -> String
+{
+    if !portable && !flatten {
+        return key.to_string();
+    }
+    // a replacement that is itself invalid would defeat the purpose
+    let replacement: char = if WINDOWS_INVALID.contains(&replacement)
+        || matches!(replacement, '/' | '\\' | '.' | ' ') || replacement.is_control() {
+        '_'
+    } else {
+        replacement
+    };
+    let components: Vec<String> = key.split(['/', '\\'])
+        .filter(|component| !component.is_empty() && *component != ".")
+        .map(|component| match portable {
+            true => portable_component(component, replacement),
+            false => component.to_string()
+        })
+        .collect();
+    if flatten {
+        return components.join(&replacement.to_string());
+    }
+    return components.join("/");
+}
@@ -0,0 +1,148 @@
+//! Downloading a single file into a stream instead of the target folder,
+//! e.g. to pipe it into another program.
+
+use std::io::Write;
+use std::time::Duration;
+
+use futures_util::StreamExt;
+
+use crate::checksum::{Checksum, StreamHasher, Verification};
+use crate::error::ZenodoDlError;
+use crate::progress::ProgressSink;
+use crate::throttle::RateLimiter;
+use crate::transport::{HttpRequest, HttpResponse, ResponseBody};
+use crate::{authorized_get, request_error, request_file, stall_watchdog, status_error, wait_for_rate_limit,
+    DownloadOptions, FileData};
+
+
+/// Continues the transfer of `entry` at `written` bytes, writing the rest
+/// to `writer`. A server answering in full instead of with the range fails
+/// the transfer, as the bytes written cannot be taken back.
+async fn transfer_from(entry: &FileData, writer: &mut (dyn Write + Send), hasher: &mut Option<StreamHasher>,
+    written: &mut u64, options: &DownloadOptions, throttle: &RateLimiter, progress: &dyn ProgressSink)
+    -> Result<(), ZenodoDlError>
+{
+    let filename: &str = &entry.filename;
+    let mut request: HttpRequest = authorized_get(&entry.url, options);
+    if *written > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", written));
+    }
+    let res: HttpResponse = request_file(request, filename, options).await?;
+    if !res.status.is_success() {
+        return Err(status_error(res, filename).await);
+    }
+    if *written > 0 && res.status != reqwest::StatusCode::PARTIAL_CONTENT {
+        return Err(ZenodoDlError::HttpStatus {
+            what: filename.to_string(),
+            status: res.status,
+            message: Some(format!("the server cannot continue at byte {} of a file already partly written",
+                written)),
+        });
+    }
+    if *written > 0 {
+        status!(options, "Resuming {} at {} bytes", filename, written);
+    } else {
+        status!(options, "Downloading {}", filename);
+    }
+
+    let mut received: u64 = 0u64;
+    progress.set_position(*written);
+    let mut stream: ResponseBody = res.body;
+    loop {
+        let item = tokio::select! {
+            biased;
+            _ = options.cancel.cancelled() =>
+                return Err(ZenodoDlError::Cancelled { what: filename.to_string() }),
+            _ = stall_watchdog(options) => {
+                writer.flush().map_err(|source|
+                    ZenodoDlError::WriteFailed { filename: filename.to_string(), source })?;
+                return Err(ZenodoDlError::Stalled { filename: filename.to_string(), received,
+                    waited: options.stall_timeout });
+            },
+            item = stream.next() => item
+        };
+        let Some(item) = item else {
+            break;
+        };
+        let chunk = item.map_err(|source| if source.is_timeout() {
+            request_error(filename, source)
+        } else {
+            ZenodoDlError::StreamInterrupted { filename: filename.to_string(), received, source }
+        })?;
+        writer.write_all(&chunk).map_err(|source|
+            ZenodoDlError::WriteFailed { filename: filename.to_string(), source })?;
+        if let Some(hasher) = hasher.as_mut() {
+            hasher.update(&chunk);
+        }
+        received += chunk.len() as u64;
+        *written += chunk.len() as u64;
+        progress.set_position(*written);
+        throttle.consume(chunk.len() as u64).await;
+    }
+    return writer.flush().map_err(|source|
+        ZenodoDlError::WriteFailed { filename: filename.to_string(), source });
+}
+
+/// Writes the content of `entry` to `writer`, hashing it on the way, and
+/// returns the bytes written. Interrupted transfers are retried per the
+/// options from where they stopped. A wrong size or checksum is only
+/// found once all bytes were written.
+pub(crate) async fn stream_file(entry: &FileData, writer: &mut (dyn Write + Send),
+    options: &DownloadOptions, progress: &dyn ProgressSink) -> Result<u64, ZenodoDlError>
+{
+    let checksum: Option<&Checksum> = entry.checksum.as_ref();
+    let mut hasher: Option<StreamHasher> = checksum.and_then(StreamHasher::for_checksum);
+    let throttle: RateLimiter = RateLimiter::new(options.limit_rate);
+    let mut written: u64 = 0u64;
+    let mut retry: u32 = 0;
+    let mut rate_limit_waited: Duration = Duration::ZERO;
+    loop {
+        let err: ZenodoDlError = match transfer_from(entry, writer, &mut hasher, &mut written, options,
+            &throttle, progress).await {
+            Ok(()) => break,
+            Err(err) => err
+        };
+        if wait_for_rate_limit(&err, &mut rate_limit_waited, options).await {
+            continue;
+        }
+        // neither can be repeated with the bytes already written
+        let repeatable: bool = !matches!(err, ZenodoDlError::SizeMismatch { .. }
+            | ZenodoDlError::ChecksumMismatch { .. });
+        if !err.is_retryable() || !repeatable || retry >= options.retry_policy.retries {
+            return Err(err);
+        }
+        let delay: Duration = options.retry_policy.backoff_delay(retry);
+        retry += 1;
+        warning!(options, "{} - retry {}/{} in {:.1}s", err, retry, options.retry_policy.retries,
+            delay.as_secs_f64());
+        tokio::select! {
+            _ = options.cancel.cancelled() => (),
+            _ = tokio::time::sleep(delay) => ()
+        };
+    }
+    progress.finish();
+
+    if let Some(expected) = entry.known_size().filter(|expected| written != *expected) {
+        return Err(ZenodoDlError::SizeMismatch {
+            filename: entry.filename.clone(),
+            expected,
+            got: written,
+        });
+    }
+    match (checksum, hasher) {
+        (Some(checksum), Some(hasher)) => {
+            if let Verification::Mismatch(actual) = hasher.verify(checksum) {
+                return Err(ZenodoDlError::ChecksumMismatch {
+                    filename: entry.filename.clone(),
+                    expected: checksum.value.clone(),
+                    actual,
+                });
+            }
+            tracing::debug!("checksum of {} verified ({})", entry.filename, checksum.algorithm);
+        },
+        (Some(_), None) => warning!(options, "unsupported checksum algorithm for {} - written unverified",
+            entry.filename),
+        (None, _) => warning!(options, "{} has no valid checksum - written unverified", entry.filename)
+    };
+    return Ok(written);
+}
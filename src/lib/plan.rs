@@ -0,0 +1,67 @@
+//! What a download run would do, as computed by a dry run.
+
+use serde::{Serialize, Deserialize};
+
+
+/// What a download run would do with a single file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlannedAction {
+    /// not present locally, would be downloaded
+    Download,
+    /// a partial file exists and would be completed
+    Resume,
+    /// present and verified (or without usable checksum), would be kept
+    SkipExisting,
+    /// present but not matching its checksum, would be downloaded again
+    Redownload,
+    /// larger than the maximum file size, would be left out
+    SkipTooLarge,
+    /// not completely uploaded to the record yet, would be left out
+    SkipIncomplete,
+    /// present, but would be downloaded again as the options ask
+    Replace,
+}
+
+impl PlannedAction {
+    /// Whether the file would be (partially) downloaded
+    pub fn transfers(&self) -> bool
+    {
+        return !matches!(self, PlannedAction::SkipExisting | PlannedAction::SkipTooLarge
+            | PlannedAction::SkipIncomplete);
+    }
+}
+
+
+/// Planned action for a single file of the record
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FilePlan {
+    pub filename: String,
+    /// size according to the record metadata
+    pub size: u64,
+    pub action: PlannedAction,
+    /// bytes that would be received over the network for this file
+    pub bytes_to_transfer: u64,
+}
+
+
+/// Planned actions for all selected files of a record
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DownloadPlan {
+    pub record_id: String,
+    pub files: Vec<FilePlan>,
+}
+
+impl DownloadPlan {
+    /// Total bytes the run would receive
+    pub fn bytes_to_transfer(&self) -> u64
+    {
+        return self.files.iter().map(|file| file.bytes_to_transfer).sum();
+    }
+
+    /// Whether any file would have to be downloaded
+    pub fn needs_download(&self) -> bool
+    {
+        return self.files.iter().any(|file| file.action.transfers());
+    }
+}
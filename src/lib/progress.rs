@@ -0,0 +1,141 @@
+//! Progress reporting of file downloads.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::error::ZenodoDlError;
+
+
+/// Receives the progress of downloads, e.g. to drive the progress display
+/// of a GUI. All methods do nothing by default. Files of a run may be
+/// downloaded concurrently, so calls for different files interleave.
+pub trait ProgressObserver: Send + Sync {
+    /// the download of `files` files starts, e.g. those of one record
+    fn download_started(&self, _files: usize) {}
+
+    /// an attempt to download a file of `size` bytes starts; called again
+    /// for each retry
+    fn file_started(&self, _filename: &str, _size: u64) {}
+
+    /// the server announced the size of a file started with a `size` of 0,
+    /// which the record leaves unknown
+    fn file_size_known(&self, _filename: &str, _size: u64) {}
+
+    /// `bytes` of the file are present so far, including those of a
+    /// resumed earlier attempt
+    fn bytes_advanced(&self, _filename: &str, _bytes: u64) {}
+
+    /// the file was downloaded and verified, or existed already
+    fn file_finished(&self, _filename: &str) {}
+
+    /// the file failed for good, after all retries
+    fn file_failed(&self, _filename: &str, _error: &ZenodoDlError) {}
+
+    /// the local copy of a file of `size` bytes is hashed, e.g. to decide
+    /// whether it can be kept
+    fn verification_started(&self, _filename: &str, _size: u64) {}
+
+    /// `bytes` of the local copy were hashed so far
+    fn verification_advanced(&self, _filename: &str, _bytes: u64) {}
+
+    /// the local copy was hashed, whatever the result
+    fn verification_finished(&self, _filename: &str) {}
+
+    /// the run is over, including when it was aborted with files still in
+    /// flight
+    fn download_finished(&self) {}
+}
+
+
+/// Ignores all progress, the default of `DownloadOptions`
+pub struct NoProgress;
+
+impl ProgressObserver for NoProgress {}
+
+
+/// Runs `f` with the progress bars of a running download cleared, so that
+/// whatever it writes to the terminal, e.g. log lines, does not tear them
+pub fn suspend_progress<R>(f: impl FnOnce() -> R) -> R
+{
+    #[cfg(feature = "cli-progress")]
+    return crate::terminal::suspend(f);
+    #[cfg(not(feature = "cli-progress"))]
+    return f();
+}
+
+
+/// Receives the progress of a single download attempt
+pub(crate) trait ProgressSink: Send + Sync {
+    /// number of bytes of the file present so far
+    fn set_position(&self, bytes: u64);
+    /// size of a file not known when the attempt started
+    fn set_length(&self, _bytes: u64) {}
+    fn finish(&self);
+}
+
+
+/// Forwards the progress of one file to the observer
+pub(crate) struct FileProgress<'a> {
+    observer: &'a dyn ProgressObserver,
+    filename: &'a str,
+}
+
+impl ProgressSink for FileProgress<'_> {
+    fn set_position(&self, bytes: u64)
+    {
+        self.observer.bytes_advanced(self.filename, bytes);
+    }
+
+    fn set_length(&self, bytes: u64)
+    {
+        self.observer.file_size_known(self.filename, bytes);
+    }
+
+    fn finish(&self) {}
+}
+
+
+/// Progress of all files of a run, reported to the observer of the
+/// options
+pub(crate) struct DownloadProgress {
+    observer: Arc<dyn ProgressObserver>,
+    finished: AtomicBool,
+}
+
+impl DownloadProgress {
+    pub(crate) fn new(observer: &Arc<dyn ProgressObserver>, files: usize) -> DownloadProgress
+    {
+        observer.download_started(files);
+        return DownloadProgress { observer: Arc::clone(observer), finished: AtomicBool::new(false) };
+    }
+
+    /// Sink for one download attempt of a file of `filesize` bytes
+    pub(crate) fn file_sink<'a>(&'a self, filename: &'a str, filesize: u64) -> FileProgress<'a>
+    {
+        self.observer.file_started(filename, filesize);
+        return FileProgress { observer: self.observer.as_ref(), filename };
+    }
+
+    /// Reports the outcome of a file, whatever it is
+    pub(crate) fn file_done<T>(&self, filename: &str, outcome: &Result<T, ZenodoDlError>)
+    {
+        match outcome {
+            Ok(_) => self.observer.file_finished(filename),
+            Err(err) => self.observer.file_failed(filename, err),
+        };
+    }
+
+    pub(crate) fn finish(&self)
+    {
+        if !self.finished.swap(true, Ordering::Relaxed) {
+            self.observer.download_finished();
+        }
+    }
+}
+
+impl Drop for DownloadProgress {
+    fn drop(&mut self)
+    {
+        self.finish();
+    }
+}
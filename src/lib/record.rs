@@ -0,0 +1,210 @@
+//! Descriptive metadata of a record from `/api/records/{id}`.
+
+use serde::{Serialize, Deserialize};
+
+use crate::error::ZenodoDlError;
+
+
+/// Author of a record
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Creator {
+    #[serde(default)]
+    pub name: String,
+    pub affiliation: Option<String>,
+    pub orcid: Option<String>,
+}
+
+/// The commonly needed fields of a record. Everything else is available
+/// through `raw`, the record JSON as returned by the API.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordMetadata {
+    pub record_id: String,
+    pub title: String,
+    pub creators: Vec<Creator>,
+    /// HTML description
+    pub description: Option<String>,
+    /// license identifier, e.g. `cc-by-4.0`
+    pub license: Option<String>,
+    /// publication date (YYYY-MM-DD)
+    pub publication_date: Option<String>,
+    pub doi: Option<String>,
+    pub version: Option<String>,
+    pub raw: serde_json::Value,
+}
+
+/// A record as found by a search, e.g. of a community
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordSummary {
+    pub record_id: String,
+    pub title: String,
+    /// bytes of all files of the record, 0 if the search does not say
+    pub size: u64,
+}
+
+/// Total size of the files of a search hit. Zenodo lists the files as
+/// array, InvenioRDM as `entries` object or just the `total_bytes`.
+pub(crate) fn files_size(files: &serde_json::Value) -> u64
+{
+    if let Some(total) = files.get("total_bytes").and_then(|total| total.as_u64()) {
+        return total;
+    }
+    let entries: Vec<&serde_json::Value> = match files {
+        serde_json::Value::Array(entries) => entries.iter().collect(),
+        serde_json::Value::Object(fields) => match fields.get("entries") {
+            Some(serde_json::Value::Array(entries)) => entries.iter().collect(),
+            Some(serde_json::Value::Object(entries)) => entries.values().collect(),
+            _ => Vec::new()
+        },
+        _ => Vec::new()
+    };
+    return entries.iter()
+        .filter_map(|entry| entry.get("size").and_then(|size| size.as_u64()))
+        .sum();
+}
+
+
+#[derive(Deserialize, Debug, Default)]
+struct RawMetadata {
+    title: Option<String>,
+    creators: Option<Vec<serde_json::Value>>,
+    description: Option<String>,
+    license: Option<serde_json::Value>,
+    /// licenses of InvenioRDM records
+    rights: Option<Vec<serde_json::Value>>,
+    publication_date: Option<String>,
+    doi: Option<String>,
+    version: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RawRecord {
+    doi: Option<String>,
+    /// persistent identifiers of InvenioRDM records, e.g.
+    /// `{"doi": {"identifier": "10.22002/..."}}`
+    pids: Option<serde_json::Value>,
+    metadata: Option<RawMetadata>,
+}
+
+
+/// Licenses are reported as `{"id": "cc-by-4.0"}` or as plain strings
+fn license_id(license: &serde_json::Value) -> Option<String>
+{
+    return match license {
+        serde_json::Value::String(id) => Some(id.clone()),
+        serde_json::Value::Object(fields) => fields.get("id")
+            .and_then(|id| id.as_str())
+            .map(|id| id.to_string()),
+        _ => None
+    };
+}
+
+/// Creators are `{"name": ..., "orcid": ...}` on Zenodo and
+/// `{"person_or_org": {"name": ..., "identifiers": [...]}, "affiliations": [...]}`
+/// on other InvenioRDM instances
+fn creator(raw: &serde_json::Value) -> Option<Creator>
+{
+    let person: &serde_json::Value = match raw.get("person_or_org") {
+        Some(person) => person,
+        None => return serde_json::from_value(raw.clone()).ok()
+    };
+    let text = |value: Option<&serde_json::Value>| value
+        .and_then(|value| value.as_str())
+        .map(|value| value.to_string());
+    let orcid: Option<String> = person.get("identifiers")
+        .and_then(|identifiers| identifiers.as_array())
+        .and_then(|identifiers| identifiers.iter()
+            .find(|identifier| identifier.get("scheme").and_then(|scheme| scheme.as_str()) == Some("orcid")))
+        .and_then(|identifier| text(identifier.get("identifier")));
+    return Some(Creator {
+        name: text(person.get("name")).unwrap_or_default(),
+        affiliation: raw.pointer("/affiliations/0/name").and_then(|name| text(Some(name))),
+        orcid,
+    });
+}
+
+
+impl RecordMetadata {
+    pub(crate) fn from_json(record_id: &str, raw: serde_json::Value)
+        -> Result<RecordMetadata, serde_json::Error>
+    {
+        let record: RawRecord = serde_json::from_value(raw.clone())?;
+        let metadata: RawMetadata = record.metadata.unwrap_or_default();
+        return Ok(RecordMetadata {
+            record_id: record_id.to_string(),
+            title: metadata.title.unwrap_or_default(),
+            creators: metadata.creators.iter().flatten().filter_map(creator).collect(),
+            description: metadata.description,
+            license: metadata.license.as_ref()
+                .or(metadata.rights.as_ref().and_then(|rights| rights.first()))
+                .and_then(license_id),
+            publication_date: metadata.publication_date,
+            doi: record.doi.or(metadata.doi).or_else(|| record.pids.as_ref()
+                .and_then(|pids| pids.pointer("/doi/identifier"))
+                .and_then(|doi| doi.as_str())
+                .map(|doi| doi.to_string())),
+            version: metadata.version,
+            raw,
+        });
+    }
+
+    /// Number and total size of the files as the record lists them, `None`
+    /// if it does not; records without files enabled have none
+    pub fn listed_files(&self) -> Option<(usize, u64)>
+    {
+        let files: &serde_json::Value = self.raw.get("files")?;
+        if files.get("enabled").and_then(|enabled| enabled.as_bool()) == Some(false) {
+            return Some((0, 0u64));
+        }
+        let count: usize = match files {
+            serde_json::Value::Array(entries) => entries.len(),
+            serde_json::Value::Object(fields) => match (fields.get("count"), fields.get("entries")) {
+                (Some(count), _) => usize::try_from(count.as_u64()?).ok()?,
+                (None, Some(serde_json::Value::Array(entries))) => entries.len(),
+                (None, Some(serde_json::Value::Object(entries))) => entries.len(),
+                _ => return None
+            },
+            _ => return None
+        };
+        return Some((count, files_size(files)));
+    }
+
+    /// Why the files cannot be downloaded as far as the access settings of
+    /// the record tell, `None` if they should be accessible
+    pub fn access_error(&self) -> Option<ZenodoDlError>
+    {
+        return access_error(&self.record_id, &self.raw);
+    }
+}
+
+
+/// Why the files of `record` cannot be listed, as far as its access
+/// settings tell: InvenioRDM has `access.status` and `access.embargo`,
+/// the legacy Zenodo format `metadata.access_right` and
+/// `metadata.embargo_date`. `None` if the files should be accessible.
+pub(crate) fn access_error(record_id: &str, record: &serde_json::Value) -> Option<ZenodoDlError>
+{
+    let text = |pointer: &str| record.pointer(pointer).and_then(|value| value.as_str())
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string());
+    let access_right: Option<String> = text("/metadata/access_right");
+    let embargoed: bool = record.pointer("/access/embargo/active").and_then(|active| active.as_bool()) == Some(true)
+        || text("/access/status").as_deref() == Some("embargoed")
+        || access_right.as_deref() == Some("embargoed");
+    if embargoed {
+        return Some(ZenodoDlError::Embargoed {
+            record_id: record_id.to_string(),
+            until: text("/access/embargo/until").or_else(|| text("/metadata/embargo_date")),
+        });
+    }
+    let restricted: bool = text("/access/files").as_deref() == Some("restricted")
+        || text("/access/status").as_deref() == Some("restricted")
+        || matches!(access_right.as_deref(), Some("restricted" | "closed"));
+    if restricted {
+        return Some(ZenodoDlError::Restricted {
+            record_id: record_id.to_string(),
+            // access is requested on the page of the record
+            record_url: text("/links/self_html").or_else(|| text("/links/html")),
+        });
+    }
+    return None;
+}
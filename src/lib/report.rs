@@ -0,0 +1,235 @@
+//! Per-file results of a download run.
+
+use serde::{Serialize, Deserialize};
+
+use crate::error::ZenodoDlError;
+use crate::existing::ExistingFileCheck;
+use crate::extract::Extraction;
+
+
+/// What happened to a single file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    /// downloaded (and verified, if possible) in this run
+    Downloaded,
+    /// existed already with a matching checksum and was kept
+    SkippedExisting,
+    /// larger than the maximum file size of the options, left out on
+    /// purpose
+    SkippedTooLarge,
+    /// still being uploaded to the record (its status is not `completed`),
+    /// so there is nothing to download yet
+    SkippedIncomplete,
+    /// could not be downloaded, see the error
+    Failed,
+    /// downloaded, but never matched its checksum
+    ChecksumMismatch,
+    /// not (completely) downloaded because the run was cancelled
+    Cancelled,
+}
+
+
+/// Outcome of a single file of the record
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FileResult {
+    pub filename: String,
+    /// size according to the record metadata
+    pub size: u64,
+    pub status: FileStatus,
+    /// bytes received over the network for this file
+    pub bytes_transferred: u64,
+    /// wall time spent on the file, including verification
+    pub elapsed_secs: f64,
+    /// downloads repeated after network errors or checksum mismatches
+    pub retries: u32,
+    /// downloads of the file started, 0 if it was not downloaded
+    #[serde(default)]
+    pub attempts: u32,
+    /// connections reopened where they broke off in the middle of the
+    /// file, see `DownloadOptions::max_reconnects`
+    #[serde(default)]
+    pub reconnects: u32,
+    /// reason for the failure, if any
+    pub error: Option<String>,
+    /// how closely the local copy of a skipped file was checked, so that
+    /// files only checked by size or existence can be verified later
+    #[serde(default)]
+    pub checked_by: Option<ExistingFileCheck>,
+    /// what became of a downloaded archive with `DownloadOptions::extract`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extraction: Option<Extraction>,
+    /// whether the file was written through to the disk, see
+    /// `DownloadOptions::fsync`
+    #[serde(default)]
+    pub fsynced: bool,
+}
+
+
+/// Counts of what happened to the files of a record
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DownloadSummary {
+    /// record id the files were downloaded from
+    pub record_id: String,
+    /// number of files listed in the record
+    pub files: usize,
+    /// files downloaded in this run
+    pub downloaded: usize,
+    /// files that existed already and were kept
+    pub skipped: usize,
+    /// files not downloaded for being larger than the maximum file size
+    #[serde(default)]
+    pub too_large: usize,
+    /// files not downloaded for not being completely uploaded to the record
+    #[serde(default)]
+    pub incomplete: usize,
+    /// files that could not be downloaded
+    pub failed: usize,
+    /// files left out because the run was cancelled
+    #[serde(default)]
+    pub cancelled: usize,
+    /// retries needed across all files
+    pub retries: u32,
+    /// bytes received over the network in this run
+    #[serde(default)]
+    pub bytes_transferred: u64,
+    /// wall time of the run
+    #[serde(default)]
+    pub elapsed_secs: f64,
+    /// average bytes per second received over the whole run
+    #[serde(default)]
+    pub bytes_per_second: f64,
+    /// names of the files that could not be downloaded
+    #[serde(default)]
+    pub failed_files: Vec<String>,
+}
+
+impl DownloadSummary {
+    /// Average bytes per second of `bytes` received in `elapsed_secs`, 0
+    /// for runs too short to tell
+    pub fn throughput(bytes: u64, elapsed_secs: f64) -> f64
+    {
+        if elapsed_secs <= 0.0 {
+            return 0.0;
+        }
+        return bytes as f64 / elapsed_secs;
+    }
+}
+
+
+/// Results of all files of a record, in the order the record lists them
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DownloadReport {
+    pub record_id: String,
+    pub files: Vec<FileResult>,
+    /// wall time of the download, shorter than the time of all files
+    /// together if several were downloaded at once
+    #[serde(default)]
+    pub elapsed_secs: f64,
+}
+
+impl DownloadReport {
+    pub fn summary(&self) -> DownloadSummary
+    {
+        let count = |status: FileStatus| -> usize {
+            self.files.iter().filter(|file| file.status == status).count()
+        };
+        let bytes_transferred: u64 = self.files.iter().map(|file| file.bytes_transferred).sum();
+        return DownloadSummary {
+            record_id: self.record_id.clone(),
+            files: self.files.len(),
+            downloaded: count(FileStatus::Downloaded),
+            skipped: count(FileStatus::SkippedExisting),
+            too_large: count(FileStatus::SkippedTooLarge),
+            incomplete: count(FileStatus::SkippedIncomplete),
+            failed: count(FileStatus::Failed) + count(FileStatus::ChecksumMismatch),
+            cancelled: count(FileStatus::Cancelled),
+            retries: self.files.iter().map(|file| file.retries).sum(),
+            bytes_transferred,
+            elapsed_secs: self.elapsed_secs,
+            bytes_per_second: DownloadSummary::throughput(bytes_transferred, self.elapsed_secs),
+            failed_files: self.files.iter()
+                .filter(|file| matches!(file.status, FileStatus::Failed | FileStatus::ChecksumMismatch))
+                .map(|file| file.filename.clone())
+                .collect(),
+        };
+    }
+}
+
+
+/// Result of one record of a multi-record run, see `download_records`
+#[derive(Debug)]
+pub struct RecordOutcome {
+    /// identifier as given by the caller
+    pub identifier: String,
+    pub result: Result<DownloadReport, ZenodoDlError>,
+}
+
+impl RecordOutcome {
+    /// Whether the record could not be resolved or any of its files failed
+    /// or was cancelled
+    pub fn failed(&self) -> bool
+    {
+        return match &self.result {
+            Ok(report) => {
+                let summary: DownloadSummary = report.summary();
+                summary.failed > 0 || summary.cancelled > 0
+            },
+            Err(_) => true
+        };
+    }
+}
+
+
+/// Layout version of `DownloadOutput`, increased on incompatible changes
+pub const OUTPUT_SCHEMA_VERSION: u32 = 1;
+
+/// Machine-readable result of downloading a record, e.g. for
+/// `--output-format json`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DownloadOutput {
+    pub schema_version: u32,
+    /// identifier as given by the user
+    pub identifier: String,
+    /// resolved record id, unless the run failed before resolving it
+    pub record_id: Option<String>,
+    /// whether every selected file is present and verified
+    pub success: bool,
+    pub summary: Option<DownloadSummary>,
+    /// bytes received over the network in this run
+    pub bytes_downloaded: u64,
+    pub files: Vec<FileResult>,
+    /// errors of failed files, or the error that ended the run
+    pub errors: Vec<String>,
+}
+
+impl DownloadOutput {
+    pub fn from_report(identifier: &str, report: &DownloadReport) -> DownloadOutput
+    {
+        let summary: DownloadSummary = report.summary();
+        return DownloadOutput {
+            schema_version: OUTPUT_SCHEMA_VERSION,
+            identifier: identifier.to_string(),
+            record_id: Some(report.record_id.clone()),
+            success: summary.failed == 0 && summary.cancelled == 0,
+            summary: Some(summary),
+            bytes_downloaded: report.files.iter().map(|file| file.bytes_transferred).sum(),
+            files: report.files.clone(),
+            errors: report.files.iter().filter_map(|file| file.error.clone()).collect(),
+        };
+    }
+
+    pub fn from_error(identifier: &str, err: &ZenodoDlError) -> DownloadOutput
+    {
+        return DownloadOutput {
+            schema_version: OUTPUT_SCHEMA_VERSION,
+            identifier: identifier.to_string(),
+            record_id: None,
+            success: false,
+            summary: None,
+            bytes_downloaded: 0,
+            files: Vec::new(),
+            errors: vec![err.to_string()],
+        };
+    }
+}
@@ -0,0 +1,43 @@
+//! Searches of the records API, e.g. `/api/records?q=creators.name:Smith`.
+
+use crate::record::RecordSummary;
+
+
+/// Order of search results
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SearchSort {
+    /// most relevant first, the default of Zenodo for queries
+    #[default]
+    BestMatch,
+    /// most recently published first
+    MostRecent,
+}
+
+impl SearchSort {
+    /// Value of the `sort` query parameter
+    pub(crate) fn as_str(&self) -> &'static str
+    {
+        return match self {
+            SearchSort::BestMatch => "bestmatch",
+            SearchSort::MostRecent => "mostrecent",
+        };
+    }
+}
+
+
+/// Records found by a search
+#[derive(Clone, Debug, Default)]
+pub struct SearchResult {
+    /// number of matching records as reported by the server, which may be
+    /// more than `records` when the search was limited
+    pub total: Option<u64>,
+    pub records: Vec<RecordSummary>,
+}
+
+
+/// Totals are plain numbers, or `{"value": n}` on older Elasticsearch
+/// based servers
+pub(crate) fn total_hits(total: &serde_json::Value) -> Option<u64>
+{
+    return total.as_u64().or_else(|| total.get("value").and_then(|value| value.as_u64()));
+}
@@ -0,0 +1,259 @@
+//! Downloads of a single large file as several byte ranges at once, for
+//! connections where one stream cannot use the whole bandwidth.
+
+use std::fs;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use futures_util::StreamExt;
+
+use crate::checksum::{Checksum, Verification};
+use crate::error::ZenodoDlError;
+use crate::etag::{response_etag, EtagStore};
+use crate::existing::{replace_file, sync_to_disk};
+use crate::progress::ProgressSink;
+use crate::throttle::RateLimiter;
+use crate::transport::{HttpRequest, HttpResponse, ResponseBody};
+use crate::{authorized_get, hash_local_file, io_error, preallocate, request_error, request_file, run_blocking,
+    set_modified_time, stall_watchdog, status_error, DownloadOptions, FileData};
+
+
+/// Segments are never smaller than this, so that small files are still
+/// downloaded in one piece
+const MIN_SEGMENT_SIZE: u64 = 4 * 1024 * 1024;
+
+
+/// Number of ranges a file of `size` bytes is split into when `segments`
+/// are asked for; 1 means a single stream
+pub(crate) fn segment_count(size: u64, segments: usize) -> u64
+{
+    return std::cmp::max(std::cmp::min(segments as u64, size / MIN_SEGMENT_SIZE), 1);
+}
+
+/// Temporary file of a segmented download. It has the final size from the
+/// start, so unlike a `.part` file its length tells nothing about what was
+/// received and it is never resumed.
+fn segments_file_path(filepath: &Path) -> PathBuf
+{
+    let mut parts_path = filepath.as_os_str().to_os_string();
+    parts_path.push(".parts");
+    return PathBuf::from(parts_path);
+}
+
+/// First and last byte of each of the `count` ranges of `size` bytes
+fn split_ranges(size: u64, count: u64) -> Vec<(u64, u64)>
+{
+    let length: u64 = size.div_ceil(count);
+    return (0..count)
+        .map(|index| (index * length, std::cmp::min((index + 1) * length, size) - 1))
+        .filter(|(first, last)| first <= last)
+        .collect();
+}
+
+fn range_request(entry: &FileData, options: &DownloadOptions, first: u64, last: u64)
+    -> HttpRequest
+{
+    return authorized_get(&entry.url, options)
+        .header(reqwest::header::RANGE, format!("bytes={}-{}", first, last));
+}
+
+
+/// Downloads `entry` as `count` byte ranges at once into a file of its
+/// final size and verifies the whole file afterwards, returning the number
+/// of bytes received. `None` if the server ignores range requests, so that
+/// the file has to be downloaded in one piece. Each range is retried on its
+/// own; the temporary file is removed if the download fails.
+pub(crate) async fn download_segmented(filepath: &Path, entry: &FileData, options: &DownloadOptions,
+    throttle: &RateLimiter, etags: &EtagStore, progress: &dyn ProgressSink, count: u64)
+    -> Result<Option<u64>, ZenodoDlError>
+{
+    let filename: &str = &entry.filename;
+    let ranges: Vec<(u64, u64)> = split_ranges(entry.size, count);
+    let (first, last) = ranges[0];
+    let first_response: HttpResponse = request_file(range_request(entry, options, first, last), filename, options)
+        .await?;
+    if first_response.status == reqwest::StatusCode::OK {
+        return Ok(None);
+    }
+    if !first_response.status.is_success() {
+        return Err(status_error(first_response, filename).await);
+    }
+    let etag: Option<String> = response_etag(&first_response);
+
+    let parts_path: PathBuf = segments_file_path(filepath);
+    let parts_file = fs::File::create(&parts_path).map_err(io_error(&parts_path))?;
+    preallocate(&parts_file, &parts_path, entry.size)?;
+    parts_file.set_len(entry.size).map_err(io_error(&parts_path))?;
+    drop(parts_file);
+    status!(options, "Downloading {} in {} segments", filename, ranges.len());
+
+    let received: AtomicU64 = AtomicU64::new(0);
+    progress.set_position(0);
+    let mut first_response = Some(first_response);
+    let segments = ranges.iter().map(|(first, last)| {
+        download_range(&parts_path, entry, options, throttle, progress, &received,
+            *first, *last, first_response.take())
+    });
+    let downloaded: Result<Vec<u64>, ZenodoDlError> = futures::future::try_join_all(segments).await;
+    progress.finish();
+    let bytes_transferred: u64 = match downloaded {
+        Ok(bytes) => bytes.iter().sum(),
+        Err(err) => {
+            let _ = fs::remove_file(&parts_path);
+            return Err(err);
+        }
+    };
+
+    // the ranges arrive in any order, so the file is hashed as a whole
+    match entry.checksum.as_ref() {
+        Some(checksum) => {
+            let (path, name, checksum, blocking_options) = (parts_path.clone(), filename.to_string(),
+                checksum.clone(), options.clone());
+            let verified = run_blocking(move || verify_parts(&path, &name, &checksum, &blocking_options)).await;
+            if let Err(err) = verified {
+                let _ = fs::remove_file(&parts_path);
+                return Err(err);
+            }
+        },
+        None => warning!(options, "{} has no valid checksum - keeping it unverified", filename)
+    };
+    replace_file(&parts_path, filepath, options)?;
+    set_modified_time(filepath, entry, options);
+    if options.fsync {
+        let synced_path: PathBuf = filepath.to_path_buf();
+        run_blocking(move || sync_to_disk(&synced_path)).await?;
+    }
+    etags.record(entry, filepath, etag);
+    return Ok(Some(bytes_transferred));
+}
+
+fn verify_parts(parts_path: &Path, filename: &str, checksum: &Checksum, options: &DownloadOptions)
+    -> Result<(), ZenodoDlError>
+{
+    return match hash_local_file(parts_path, filename, checksum, options)? {
+        Verification::Match => {
+            tracing::debug!("checksum of {} verified ({})", filename, &checksum.algorithm);
+            Ok(())
+        },
+        Verification::Mismatch(actual) => {
+            warning!(options, "checksum of {} does not match - deleting file", filename);
+            Err(ZenodoDlError::ChecksumMismatch {
+                filename: filename.to_string(),
+                expected: checksum.value.clone(),
+                actual,
+            })
+        },
+        // unsupported algorithms were reported before the download
+        Verification::Unsupported(_) => Ok(())
+    };
+}
+
+
+/// Downloads the bytes `first..=last` into their place in the file,
+/// retrying from the last byte received per the retry policy of the
+/// options
+#[allow(clippy::too_many_arguments)]
+async fn download_range(parts_path: &Path, entry: &FileData, options: &DownloadOptions,
+    throttle: &RateLimiter, progress: &dyn ProgressSink, received: &AtomicU64,
+    first: u64, last: u64, mut response: Option<HttpResponse>) -> Result<u64, ZenodoDlError>
+{
+    let mut position: u64 = first;
+    let mut retry: u32 = 0;
+    loop {
+        let response: Option<HttpResponse> = response.take();
+        let err: ZenodoDlError = match receive_range(parts_path, entry, options, throttle, progress,
+            received, &mut position, last, response).await {
+            Ok(()) => return Ok(last + 1 - first),
+            Err(err) => err
+        };
+        if !err.is_retryable() || retry >= options.retry_policy.retries {
+            return Err(err);
+        }
+        let delay: Duration = options.retry_policy.backoff_delay(retry);
+        retry += 1;
+        warning!(options, "segment {}-{} of {}: {} - retry {}/{} in {:.1}s", first, last,
+            entry.filename, err, retry, options.retry_policy.retries, delay.as_secs_f64());
+        tokio::select! {
+            _ = options.cancel.cancelled() => (),
+            _ = tokio::time::sleep(delay) => ()
+        };
+    }
+}
+
+/// Receives the bytes from `position` up to `last`, advancing `position`
+/// with every chunk written
+#[allow(clippy::too_many_arguments)]
+async fn receive_range(parts_path: &Path, entry: &FileData, options: &DownloadOptions,
+    throttle: &RateLimiter, progress: &dyn ProgressSink, received: &AtomicU64,
+    position: &mut u64, last: u64, response: Option<HttpResponse>) -> Result<(), ZenodoDlError>
+{
+    let filename: &str = &entry.filename;
+    if options.cancel.is_cancelled() {
+        return Err(ZenodoDlError::Cancelled { what: filename.to_string() });
+    }
+    let res: HttpResponse = match response {
+        Some(res) => res,
+        None => request_file(range_request(entry, options, *position, last), filename, options).await?
+    };
+    if res.status != reqwest::StatusCode::PARTIAL_CONTENT {
+        return Err(status_error(res, filename).await);
+    }
+
+    let mut file = fs::OpenOptions::new().write(true).open(parts_path).map_err(io_error(parts_path))?;
+    file.seek(SeekFrom::Start(*position)).map_err(io_error(parts_path))?;
+    let mut file = BufWriter::with_capacity(options.write_buffer_size, file);
+    let expected: u64 = last + 1 - *position;
+    let mut segment_received: u64 = 0u64;
+    let mut stream: ResponseBody = res.body;
+    loop {
+        let item = tokio::select! {
+            biased;
+            _ = options.cancel.cancelled() => {
+                return Err(ZenodoDlError::Cancelled { what: filename.to_string() });
+            },
+            _ = stall_watchdog(options) => {
+                // the retry continues at `position`, which counts the buffered bytes
+                file.flush().map_err(io_error(parts_path))?;
+                return Err(ZenodoDlError::Stalled { filename: filename.to_string(), received: segment_received,
+                    waited: options.stall_timeout });
+            },
+            item = stream.next() => item
+        };
+        let Some(item) = item else {
+            break;
+        };
+        let chunk = item.map_err(|source| if source.is_timeout() {
+            request_error(filename, source)
+        } else {
+            ZenodoDlError::StreamInterrupted {
+                filename: filename.to_string(),
+                received: segment_received,
+                source,
+            }
+        })?;
+        let length: u64 = chunk.len() as u64;
+        if *position + length > last + 1 {
+            return Err(ZenodoDlError::SizeMismatch {
+                filename: filename.to_string(),
+                expected,
+                got: segment_received + length,
+            });
+        }
+        file.write_all(&chunk).map_err(io_error(parts_path))?;
+        *position += length;
+        segment_received += length;
+        progress.set_position(received.fetch_add(length, Ordering::Relaxed) + length);
+        throttle.consume(length).await;
+    }
+    file.flush().map_err(io_error(parts_path))?;
+    if *position != last + 1 {
+        return Err(ZenodoDlError::SizeMismatch {
+            filename: filename.to_string(),
+            expected,
+            got: segment_received,
+        });
+    }
+    return Ok(());
+}
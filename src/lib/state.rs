@@ -0,0 +1,261 @@
+//! State of the downloads into a target folder: the files of the job as
+//! listed when it started, how far each got and the results of the last
+//! run, kept so that a later run can resume the job or retry only the
+//! files that failed.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Serialize, Deserialize};
+
+use crate::checksum::Checksum;
+use crate::error::ZenodoDlError;
+use crate::report::{DownloadReport, FileResult, FileStatus};
+use crate::{partial_file_path, write_json, FileData, STATE_FILENAME};
+
+
+/// Layout version of the state file, increased on incompatible changes;
+/// files of an unknown version are refused rather than misread
+const STATE_VERSION: u32 = 2;
+/// Oldest layout still read, version 1 only held the report
+const MIN_STATE_VERSION: u32 = 1;
+
+
+/// How far a file of the job got
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum JobStatus {
+    Pending,
+    /// partly received, the rest is resumed from its `.part` file
+    Partial,
+    Done,
+    Failed,
+}
+
+
+/// A file of the job as listed when the job started
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct JobFile {
+    filename: String,
+    local_name: String,
+    checksum: Option<String>,
+    url: String,
+    size: u64,
+    #[serde(default)]
+    mimetype: String,
+    #[serde(default)]
+    created: Option<SystemTime>,
+    #[serde(default)]
+    updated: Option<SystemTime>,
+    /// still being uploaded to the record when the job started
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    incomplete: bool,
+    pub(crate) status: JobStatus,
+    /// bytes received so far
+    pub(crate) bytes: u64,
+}
+
+impl JobFile {
+    fn new(entry: &FileData, status: JobStatus, bytes: u64) -> JobFile
+    {
+        return JobFile {
+            filename: entry.filename.clone(),
+            local_name: entry.local_name.clone(),
+            checksum: entry.checksum.as_ref()
+                .map(|checksum| format!("{}:{}", checksum.algorithm, checksum.value)),
+            url: entry.url.clone(),
+            size: entry.size,
+            mimetype: entry.mimetype.clone(),
+            created: entry.created,
+            updated: entry.updated,
+            incomplete: entry.incomplete,
+            status,
+            bytes,
+        };
+    }
+
+    /// The file as listed by the record when the job started
+    pub(crate) fn entry(&self) -> FileData
+    {
+        return FileData {
+            filename: self.filename.clone(),
+            local_name: self.local_name.clone(),
+            checksum: self.checksum.as_deref().and_then(Checksum::parse),
+            url: self.url.clone(),
+            size: self.size,
+            mimetype: self.mimetype.clone(),
+            created: self.created,
+            updated: self.updated,
+            incomplete: self.incomplete,
+        };
+    }
+
+    /// Whether `entry` is still the file this one was listed as
+    pub(crate) fn same_file(&self, entry: &FileData) -> bool
+    {
+        let listed: FileData = self.entry();
+        return listed.filename == entry.filename && listed.local_name == entry.local_name
+            && listed.size == entry.size && listed.checksum == entry.checksum;
+    }
+}
+
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct DownloadState {
+    version: u32,
+    /// `updated` of the record when its files were listed, if known
+    #[serde(default)]
+    pub(crate) updated: Option<String>,
+    /// files of the job, in the order of the record
+    #[serde(default)]
+    pub(crate) files: Vec<JobFile>,
+    /// results of the files as of their last run
+    pub(crate) report: DownloadReport,
+}
+
+impl DownloadState {
+    fn new(record_id: &str) -> DownloadState
+    {
+        return DownloadState {
+            version: STATE_VERSION,
+            updated: None,
+            files: Vec::new(),
+            report: DownloadReport { record_id: record_id.to_string(), ..DownloadReport::default() },
+        };
+    }
+
+    /// The job file of the file with key `filename`
+    pub(crate) fn file(&self, filename: &str) -> Option<&JobFile>
+    {
+        return self.files.iter().find(|file| file.filename == filename);
+    }
+}
+
+
+/// Whether a file of the report has to be downloaded again
+pub(crate) fn needs_retry(result: &FileResult) -> bool
+{
+    return matches!(result.status,
+        FileStatus::Failed | FileStatus::ChecksumMismatch | FileStatus::Cancelled);
+}
+
+/// Bytes of `entry` received so far, as kept in its `.part` file
+fn partial_bytes(target_folder: &str, entry: &FileData) -> u64
+{
+    let filepath: PathBuf = Path::new(target_folder).join(&entry.local_name);
+    return fs::metadata(partial_file_path(&filepath)).map(|meta| meta.len()).unwrap_or(0);
+}
+
+/// The state of the downloads into `target_folder`, as saved by `JobState`
+pub(crate) fn load_state(target_folder: &str) -> Result<DownloadState, ZenodoDlError>
+{
+    let path: PathBuf = Path::new(target_folder).join(STATE_FILENAME);
+    let invalid = |reason: String| ZenodoDlError::InvalidState { path: path.clone(), reason };
+    let content: String = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound =>
+            return Err(ZenodoDlError::NoState { path }),
+        Err(err) => return Err(invalid(err.to_string()))
+    };
+    let value: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|err| invalid(err.to_string()))?;
+    let version: u64 = value.get("version").and_then(|version| version.as_u64())
+        .ok_or_else(|| invalid("no version".to_string()))?;
+    if version < MIN_STATE_VERSION as u64 || version > STATE_VERSION as u64 {
+        return Err(invalid(format!("unsupported version {}, expected {} to {}", version,
+            MIN_STATE_VERSION, STATE_VERSION)));
+    }
+    let mut state: DownloadState = serde_json::from_value(value).map_err(|err| invalid(err.to_string()))?;
+    state.version = STATE_VERSION;
+    return Ok(state);
+}
+
+
+/// State of the downloads into a target folder while files are downloaded,
+/// saved after every file so that a crash loses no finished file
+pub(crate) struct JobState {
+    /// `None` if the options turned the state off
+    path: Option<PathBuf>,
+    target_folder: String,
+    state: DownloadState,
+}
+
+impl JobState {
+    /// Starts downloading `files` of `record_id`. Other files of the same
+    /// record keep their state, so that downloading some files only updates
+    /// theirs; the state of another record is replaced. `updated` of `None`
+    /// keeps the one saved.
+    pub(crate) fn start(target_folder: &str, record_id: &str, updated: Option<String>,
+        files: &[FileData], save_state: bool) -> JobState
+    {
+        if !save_state {
+            return JobState {
+                path: None,
+                target_folder: target_folder.to_string(),
+                state: DownloadState::new(record_id),
+            };
+        }
+        let mut state: DownloadState = match load_state(target_folder) {
+            Ok(previous) if previous.report.record_id == record_id => previous,
+            _ => DownloadState::new(record_id)
+        };
+        if updated.is_some() {
+            state.updated = updated;
+        }
+        for entry in files.iter() {
+            let bytes: u64 = partial_bytes(target_folder, entry);
+            let status: JobStatus = if bytes > 0 { JobStatus::Partial } else { JobStatus::Pending };
+            let file: JobFile = JobFile::new(entry, status, bytes);
+            match state.files.iter_mut().find(|old| old.filename == entry.filename) {
+                Some(old) => *old = file,
+                None => state.files.push(file)
+            };
+        }
+        return JobState { path: Some(Path::new(target_folder).join(STATE_FILENAME)),
+            target_folder: target_folder.to_string(), state };
+    }
+
+    /// Records the result of `entry`
+    pub(crate) fn finish(&mut self, entry: &FileData, result: &FileResult)
+    {
+        if self.path.is_none() {
+            return;
+        }
+        let partial: u64 = partial_bytes(&self.target_folder, entry);
+        let (status, bytes): (JobStatus, u64) = match result.status {
+            FileStatus::Downloaded | FileStatus::SkippedExisting => (JobStatus::Done, entry.size),
+            FileStatus::Failed | FileStatus::ChecksumMismatch => (JobStatus::Failed, partial),
+            FileStatus::Cancelled if partial > 0 => (JobStatus::Partial, partial),
+            FileStatus::Cancelled | FileStatus::SkippedTooLarge | FileStatus::SkippedIncomplete =>
+                (JobStatus::Pending, 0)
+        };
+        if let Some(file) = self.state.files.iter_mut().find(|file| file.filename == entry.filename) {
+            file.status = status;
+            file.bytes = bytes;
+        }
+        let files: &mut Vec<FileResult> = &mut self.state.report.files;
+        match files.iter_mut().find(|old| old.filename == result.filename) {
+            Some(old) => *old = result.clone(),
+            None => files.push(result.clone())
+        };
+    }
+
+    /// Records the wall time of the run that produced `report`
+    pub(crate) fn complete(&mut self, report: &DownloadReport)
+    {
+        self.state.report.elapsed_secs = report.elapsed_secs;
+    }
+
+    /// Writes the state into the target folder
+    pub(crate) fn save(&self) -> Result<(), ZenodoDlError>
+    {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        let value: serde_json::Value = serde_json::to_value(&self.state).map_err(|source|
+            ZenodoDlError::JsonParse { what: path.display().to_string(), source })?;
+        return write_json(path, &value);
+    }
+}
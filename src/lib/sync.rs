@@ -0,0 +1,118 @@
+//! Local files that belong to no file of a record, e.g. files removed from
+//! the record in a newer version, for keeping a target folder an exact
+//! mirror of the record.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+
+use crate::checksum::ChecksumAlgorithm;
+use crate::error::ZenodoDlError;
+use crate::manifest::manifest_filename;
+use crate::{io_error, CITATION_FILENAME};
+
+
+/// Below this share of local files belonging to the record, the target
+/// folder is taken for one the record was never downloaded to
+const MIN_MATCHING_SHARE: f64 = 0.5;
+
+
+/// Local files of a target folder that belong to no file of a record
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StaleFiles {
+    pub record_id: String,
+    /// paths relative to the target folder with `/` as separator, sorted
+    pub files: Vec<String>,
+    /// local files that belong to the record
+    pub matching: usize,
+}
+
+
+/// Whether `relative_path` was written by a download of the record and is
+/// not one of its files: manifests, the citation, partial downloads and
+/// old copies kept by `ExistingFilePolicy::Rename`
+fn written_by_download(relative_path: &str, expected: &HashSet<String>) -> bool
+{
+    let algorithms = [ChecksumAlgorithm::Md5, ChecksumAlgorithm::Sha1, ChecksumAlgorithm::Sha256,
+        ChecksumAlgorithm::Sha512];
+    if relative_path == CITATION_FILENAME
+        || algorithms.iter().any(|algorithm| relative_path == manifest_filename(*algorithm)) {
+        return true;
+    }
+    let stem: &str = match relative_path.rsplit_once('.') {
+        Some((stem, "part" | "parts")) => stem,
+        Some((stem, suffix)) if suffix.strip_prefix("old-")
+            .is_some_and(|index| index.parse::<u32>().is_ok()) => stem,
+        _ => return false
+    };
+    return expected.contains(stem);
+}
+
+/// Adds the regular files below `folder` to `files`, as paths relative to
+/// the target folder. Hidden files and folders, e.g. the metadata written
+/// with the downloads or a `.git` folder, and symlinks are left out.
+fn collect_files(folder: &Path, prefix: &str, files: &mut Vec<String>) -> Result<(), ZenodoDlError>
+{
+    for dir_entry in fs::read_dir(folder).map_err(io_error(folder))? {
+        let dir_entry: fs::DirEntry = dir_entry.map_err(io_error(folder))?;
+        let name: String = dir_entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+        let relative_path: String = format!("{}{}", prefix, name);
+        let file_type: fs::FileType = dir_entry.file_type().map_err(io_error(&dir_entry.path()))?;
+        if file_type.is_dir() {
+            collect_files(&dir_entry.path(), &format!("{}/", relative_path), files)?;
+        } else if file_type.is_file() {
+            files.push(relative_path);
+        }
+    }
+    return Ok(());
+}
+
+/// Local files in `target_folder` that are neither one of the `expected`
+/// local names nor written by a download. Fails if less than half of the
+/// local files belong to the record, as the folder then rather looks like
+/// one the record was never downloaded to.
+pub(crate) fn stale_files(record_id: &str, target_folder: &str, expected: &HashSet<String>)
+    -> Result<StaleFiles, ZenodoDlError>
+{
+    let mut local: Vec<String> = Vec::new();
+    collect_files(Path::new(target_folder), "", &mut local)?;
+    let mut matching: usize = 0;
+    let mut files: Vec<String> = Vec::new();
+    for relative_path in local.into_iter() {
+        if expected.contains(&relative_path) {
+            matching += 1;
+        } else if !written_by_download(&relative_path, expected) {
+            files.push(relative_path);
+        }
+    }
+    let total: usize = matching + files.len();
+    if !files.is_empty() && (matching as f64) < total as f64 * MIN_MATCHING_SHARE {
+        return Err(ZenodoDlError::UnrelatedFolder {
+            path: Path::new(target_folder).to_path_buf(),
+            matching,
+            files: total,
+        });
+    }
+    files.sort();
+    return Ok(StaleFiles { record_id: record_id.to_string(), files, matching });
+}
+
+/// Removes `relative_path` below `target_folder` and the folders it leaves
+/// empty
+pub(crate) fn remove_stale_file(target_folder: &Path, relative_path: &str) -> Result<(), ZenodoDlError>
+{
+    let path = target_folder.join(relative_path);
+    fs::remove_file(&path).map_err(io_error(&path))?;
+    for folder in path.ancestors().skip(1) {
+        // stops at the first folder that still holds something
+        if folder == target_folder || fs::remove_dir(folder).is_err() {
+            break;
+        }
+    }
+    return Ok(());
+}
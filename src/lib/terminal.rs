@@ -0,0 +1,330 @@
+//! Progress bars on the terminal, enabled by the `cli-progress` feature.
+
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
+
+use crate::error::ZenodoDlError;
+use crate::progress::{ProgressObserver, ProgressSink};
+
+
+/// Bars of files finishing faster than this are never drawn
+const BAR_DELAY: Duration = Duration::from_secs(1);
+/// Interval of the plain-text status lines used instead of bars
+const STATUS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Bars of the download drawn at the moment, see `suspend_progress`
+static ACTIVE_BARS: Mutex<Option<MultiProgress>> = Mutex::new(None);
+
+
+pub(crate) fn suspend<R>(f: impl FnOnce() -> R) -> R
+{
+    let multi: Option<MultiProgress> = ACTIVE_BARS.lock().ok().and_then(|active| active.clone());
+    return match multi {
+        Some(multi) => multi.suspend(f),
+        None => f()
+    };
+}
+
+
+/// Bar of a file of known size
+const BAR_TEMPLATE: &str = "{spinner:.green} [{elapsed_precise}] [{wide_bar:.green/green}] {bytes}/{total_bytes} ({bytes_per_sec} [eta: {eta}]) {msg}";
+/// Bar of a file of unknown size, which cannot tell how far along it is
+const SPINNER_TEMPLATE: &str = "{spinner:.green} [{elapsed_precise}] {bytes} ({bytes_per_sec}) {msg}";
+
+
+fn style(template: &str) -> ProgressStyle
+{
+    // a template that fails to parse falls back to the plain default bar,
+    // so a bar never keeps a file from being downloaded
+    return ProgressStyle::default_bar()
+        .template(template)
+        .unwrap_or_else(|_| ProgressStyle::default_bar())
+        .progress_chars("#>-");
+}
+
+
+/// Bars shared by all files of a run, plus the summary line at the bottom
+struct Bars {
+    multi: MultiProgress,
+    summary: ProgressBar,
+    received: AtomicU64,
+}
+
+
+/// Bar of a single file, only added to the terminal once the file takes
+/// longer than `BAR_DELAY`
+struct FileBar {
+    bar: ProgressBar,
+    bars: Arc<Bars>,
+    started: Instant,
+    shown: AtomicBool,
+    /// last reported position, `u64::MAX` before the first report
+    position: AtomicU64,
+    /// whether the bytes were downloaded rather than hashed, which the
+    /// summary line counts
+    downloading: bool,
+}
+
+impl ProgressSink for FileBar {
+    fn set_position(&self, bytes: u64)
+    {
+        let previous: u64 = self.position.swap(bytes, Ordering::Relaxed);
+        // the first position is where a resumed file starts, not progress
+        if self.downloading && previous != u64::MAX && bytes > previous {
+            let received: u64 = self.bars.received.fetch_add(bytes - previous, Ordering::Relaxed)
+                + bytes - previous;
+            self.bars.summary.set_message(format!("{} received", HumanBytes(received)));
+        }
+        self.bar.set_position(bytes);
+        if self.bar.length().is_none() {
+            // nothing moves a bar without length but its ticks
+            self.bar.tick();
+        }
+        if !self.shown.load(Ordering::Relaxed) && self.started.elapsed() >= BAR_DELAY {
+            self.shown.store(true, Ordering::Relaxed);
+            self.bars.multi.insert_before(&self.bars.summary, self.bar.clone());
+        }
+    }
+
+    fn set_length(&self, bytes: u64)
+    {
+        self.bar.set_length(bytes);
+        self.bar.set_style(style(BAR_TEMPLATE));
+    }
+
+    fn finish(&self)
+    {
+        if self.shown.swap(false, Ordering::Relaxed) {
+            self.bar.finish_and_clear();
+            self.bars.multi.remove(&self.bar);
+        }
+    }
+}
+
+/// Downloads that are cancelled, e.g. by Ctrl-C, must not leave their bar
+impl Drop for FileBar {
+    fn drop(&mut self)
+    {
+        self.finish();
+    }
+}
+
+
+/// Periodic status lines for terminals that cannot show bars, e.g. logs
+struct StatusLines {
+    label: String,
+    /// 0 while unknown
+    filesize: AtomicU64,
+    last_line: Mutex<Instant>,
+}
+
+impl ProgressSink for StatusLines {
+    fn set_position(&self, bytes: u64)
+    {
+        if let Ok(mut last_line) = self.last_line.lock() {
+            if last_line.elapsed() >= STATUS_INTERVAL {
+                *last_line = Instant::now();
+                match self.filesize.load(Ordering::Relaxed) {
+                    0 => tracing::info!("{}: {}", self.label, HumanBytes(bytes)),
+                    filesize => tracing::info!("{}: {} of {}", self.label, HumanBytes(bytes), HumanBytes(filesize))
+                };
+            }
+        }
+    }
+
+    fn set_length(&self, bytes: u64)
+    {
+        self.filesize.store(bytes, Ordering::Relaxed);
+    }
+
+    fn finish(&self) {}
+}
+
+
+enum Mode {
+    Idle,
+    Bars(Arc<Bars>),
+    StatusLines,
+}
+
+
+/// Draws a bar for each file in flight above a summary line when stderr
+/// and stdout are terminals, otherwise logs plain status lines
+pub struct TerminalProgress {
+    mode: Mutex<Mode>,
+    files: Mutex<HashMap<String, Box<dyn ProgressSink>>>,
+}
+
+impl TerminalProgress {
+    pub fn new() -> TerminalProgress
+    {
+        return TerminalProgress {
+            mode: Mutex::new(Mode::Idle),
+            files: Mutex::new(HashMap::new()),
+        };
+    }
+
+    /// Adds the bar or status lines of a file, replacing those of an earlier
+    /// attempt or of its verification
+    fn add_sink(&self, filename: &str, size: u64, label: String, downloading: bool)
+    {
+        let mode = match self.mode.lock() {
+            Ok(mode) => mode,
+            Err(_) => return
+        };
+        let sink: Box<dyn ProgressSink> = match &*mode {
+            Mode::Idle => return,
+            Mode::Bars(bars) => {
+                let bar = ProgressBar::hidden();
+                if size > 0 {
+                    bar.set_length(size);
+                    bar.set_style(style(BAR_TEMPLATE));
+                } else {
+                    // without a length until the response may tell it, see
+                    // `set_length`
+                    bar.set_style(style(SPINNER_TEMPLATE));
+                }
+                bar.set_message(label);
+                Box::new(FileBar {
+                    bar,
+                    bars: Arc::clone(bars),
+                    started: Instant::now(),
+                    shown: AtomicBool::new(false),
+                    position: AtomicU64::new(u64::MAX),
+                    downloading,
+                })
+            },
+            Mode::StatusLines => Box::new(StatusLines {
+                label,
+                filesize: AtomicU64::new(size),
+                last_line: Mutex::new(Instant::now()),
+            })
+        };
+        drop(mode);
+        if let Ok(mut files) = self.files.lock() {
+            if let Some(previous) = files.insert(filename.to_string(), sink) {
+                previous.finish();
+            }
+        }
+    }
+
+    /// Removes the sink of a file
+    fn remove_sink(&self, filename: &str)
+    {
+        if let Ok(mut files) = self.files.lock() {
+            if let Some(sink) = files.remove(filename) {
+                sink.finish();
+            }
+        }
+    }
+
+    /// Removes the sink of a file, counting it as done in the summary line
+    fn file_done(&self, filename: &str)
+    {
+        self.remove_sink(filename);
+        if let Ok(mode) = self.mode.lock() {
+            if let Mode::Bars(bars) = &*mode {
+                bars.summary.inc(1);
+            }
+        }
+    }
+}
+
+impl Default for TerminalProgress {
+    fn default() -> Self
+    {
+        return TerminalProgress::new();
+    }
+}
+
+impl ProgressObserver for TerminalProgress {
+    fn download_started(&self, files: usize)
+    {
+        // bars are drawn to stderr, status lines logged
+        let mode: Mode = if std::io::stderr().is_terminal() && std::io::stdout().is_terminal() {
+            let multi = MultiProgress::new();
+            let summary = multi.add(ProgressBar::new(files as u64));
+            summary.set_style(style("{spinner:.green} [{elapsed_precise}] {pos}/{len} files done, {msg}"));
+            summary.set_message(format!("{} received", HumanBytes(0)));
+            summary.enable_steady_tick(Duration::from_millis(250));
+            if let Ok(mut active) = ACTIVE_BARS.lock() {
+                *active = Some(multi.clone());
+            }
+            Mode::Bars(Arc::new(Bars { multi, summary, received: AtomicU64::new(0) }))
+        } else {
+            Mode::StatusLines
+        };
+        if let Ok(mut current) = self.mode.lock() {
+            *current = mode;
+        }
+    }
+
+    fn file_started(&self, filename: &str, size: u64)
+    {
+        self.add_sink(filename, size, filename.to_string(), true);
+    }
+
+    fn file_size_known(&self, filename: &str, size: u64)
+    {
+        if let Ok(files) = self.files.lock() {
+            if let Some(sink) = files.get(filename) {
+                sink.set_length(size);
+            }
+        }
+    }
+
+    fn bytes_advanced(&self, filename: &str, bytes: u64)
+    {
+        if let Ok(files) = self.files.lock() {
+            if let Some(sink) = files.get(filename) {
+                sink.set_position(bytes);
+            }
+        }
+    }
+
+    fn verification_started(&self, filename: &str, size: u64)
+    {
+        self.add_sink(filename, size, format!("Verifying {}", filename), false);
+    }
+
+    fn verification_advanced(&self, filename: &str, bytes: u64)
+    {
+        self.bytes_advanced(filename, bytes);
+    }
+
+    fn verification_finished(&self, filename: &str)
+    {
+        self.remove_sink(filename);
+    }
+
+    fn file_finished(&self, filename: &str)
+    {
+        self.file_done(filename);
+    }
+
+    fn file_failed(&self, filename: &str, _error: &ZenodoDlError)
+    {
+        self.file_done(filename);
+    }
+
+    fn download_finished(&self)
+    {
+        if let Ok(mut files) = self.files.lock() {
+            files.clear();
+        }
+        if let Ok(mut mode) = self.mode.lock() {
+            if let Mode::Bars(bars) = &*mode {
+                bars.summary.finish_and_clear();
+                if let Ok(mut active) = ACTIVE_BARS.lock() {
+                    *active = None;
+                }
+            }
+            *mode = Mode::Idle;
+        }
+    }
+}
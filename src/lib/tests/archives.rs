@@ -0,0 +1,177 @@
+//! Archives unpacked and `.gz` files decompressed after or while they are
+//! downloaded.
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::fs;
+use std::io::{Cursor, Write};
+
+use common::{content_path, md5_hex, Zenodo};
+use zenodo_dl_core::{download_record, verify_local_files, DownloadReport, Extraction, FileResult, FileStatus,
+    LocalFileStatus, VerificationReport};
+
+
+fn zip_archive(files: &[(&str, &[u8])]) -> Vec<u8>
+{
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, data) in files.iter() {
+        zip.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(data).unwrap();
+    }
+    return zip.finish().unwrap().into_inner();
+}
+
+fn tar_archive(files: &[(&str, &[u8])]) -> Vec<u8>
+{
+    let mut tar = tar::Builder::new(Vec::new());
+    for (name, data) in files.iter() {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, name, *data).unwrap();
+    }
+    return tar.into_inner().unwrap();
+}
+
+fn gzip(data: &[u8]) -> Vec<u8>
+{
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data).unwrap();
+    return encoder.finish().unwrap();
+}
+
+fn extraction(result: &FileResult) -> Option<Extraction>
+{
+    assert_eq!(result.status, FileStatus::Downloaded, "{}: {:?}", result.filename, result.error);
+    return result.extraction.clone();
+}
+
+
+#[tokio::test]
+async fn archives_are_unpacked_by_extension_or_magic_bytes()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let zip: Vec<u8> = zip_archive(&[("a.txt", b"a"), ("dir/b.txt", b"b")]);
+    let tar_gz: Vec<u8> = gzip(&tar_archive(&[("c.txt", b"c")]));
+    let tar_zst: Vec<u8> = zstd::encode_all(&tar_archive(&[("d.txt", b"d")])[..], 0).unwrap();
+    let tar: Vec<u8> = tar_archive(&[("e.txt", b"e")]);
+    zenodo.record("60", &[("data.zip", &zip), ("set.tar.gz", &tar_gz), ("set.tar.zst", &tar_zst),
+        ("no-extension", &tar), ("notes.txt", b"no archive"), ("old.rar", b"Rar!\x1a\x07\x00")]).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).extract(true).build().unwrap();
+
+    let report: DownloadReport = download_record("60", &options).await.unwrap();
+
+    let extracted = |files: usize| Some(Extraction::Extracted { folder: ".".to_string(), files, removed: false });
+    assert_eq!(extraction(&report.files[0]), extracted(2));
+    assert_eq!(extraction(&report.files[1]), extracted(1));
+    assert_eq!(extraction(&report.files[2]), extracted(1));
+    assert_eq!(extraction(&report.files[3]), extracted(1));
+    assert_eq!(extraction(&report.files[4]), None);
+    assert_eq!(extraction(&report.files[5]), Some(Extraction::Unsupported));
+    for (name, content) in [("a.txt", "a"), ("dir/b.txt", "b"), ("c.txt", "c"), ("d.txt", "d"), ("e.txt", "e")] {
+        assert_eq!(fs::read_to_string(folder.path().join(name)).unwrap(), content);
+    }
+    // the archives are kept unless asked otherwise
+    assert!(folder.path().join("data.zip").exists());
+}
+
+#[tokio::test]
+async fn archives_are_unpacked_into_subfolders_and_removed()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let zip: Vec<u8> = zip_archive(&[("a.txt", b"a")]);
+    let tar_gz: Vec<u8> = gzip(&tar_archive(&[("c.txt", b"c")]));
+    zenodo.record("61", &[("data.zip", &zip), ("sets/set.tar.gz", &tar_gz)]).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).extract(true).extract_subfolders(true).remove_archives(true)
+        .build().unwrap();
+
+    let report: DownloadReport = download_record("61", &options).await.unwrap();
+
+    assert_eq!(extraction(&report.files[0]),
+        Some(Extraction::Extracted { folder: "data".to_string(), files: 1, removed: true }));
+    assert_eq!(extraction(&report.files[1]),
+        Some(Extraction::Extracted { folder: "sets/set".to_string(), files: 1, removed: true }));
+    assert_eq!(fs::read_to_string(folder.path().join("data/a.txt")).unwrap(), "a");
+    assert_eq!(fs::read_to_string(folder.path().join("sets/set/c.txt")).unwrap(), "c");
+    assert!(!folder.path().join("data.zip").exists());
+    assert!(!folder.path().join("sets/set.tar.gz").exists());
+}
+
+#[tokio::test]
+async fn archives_leading_outside_are_not_unpacked()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let evil: Vec<u8> = zip_archive(&[("ok.txt", b"ok"), ("../evil.txt", b"evil")]);
+    zenodo.record("62", &[("evil.zip", &evil), ("broken.zip", b"PK\x03\x04 but nothing else")]).await;
+    let parent = tempfile::tempdir().unwrap();
+    let folder = parent.path().join("target");
+    fs::create_dir(&folder).unwrap();
+    let options = zenodo.options(&folder).extract(true).build().unwrap();
+
+    let report: DownloadReport = download_record("62", &options).await.unwrap();
+
+    // the downloads themselves are fine
+    assert!(matches!(extraction(&report.files[0]), Some(Extraction::Failed { .. })));
+    assert!(matches!(extraction(&report.files[1]), Some(Extraction::Failed { .. })));
+    assert!(!folder.join("ok.txt").exists());
+    assert!(!parent.path().join("evil.txt").exists());
+    assert!(folder.join("evil.zip").exists());
+}
+
+#[tokio::test]
+async fn gz_files_are_stored_decompressed()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let table: Vec<u8> = b"a,b\n1,2\n".repeat(1000);
+    let compressed: Vec<u8> = gzip(&table);
+    // files compressed in several parts, e.g. by concatenating them
+    let parts: Vec<u8> = [gzip(b"first\n"), gzip(b"second\n")].concat();
+    zenodo.record("63", &[("table.csv.gz", &compressed), ("parts.txt.gz", &parts)]).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).decompress(true).write_manifest(true).build().unwrap();
+
+    let report: DownloadReport = download_record("63", &options).await.unwrap();
+
+    assert_eq!(report.files[0].status, FileStatus::Downloaded, "{:?}", report.files[0].error);
+    assert_eq!(fs::read(folder.path().join("table.csv")).unwrap(), table);
+    assert_eq!(fs::read(folder.path().join("parts.txt")).unwrap(), b"first\nsecond\n");
+    assert!(!folder.path().join("table.csv.gz").exists());
+    let manifest: String = fs::read_to_string(folder.path().join("MD5SUMS")).unwrap();
+    assert!(manifest.contains(&format!("# table.csv decompressed from table.csv.gz  {}\n{}  table.csv\n",
+        md5_hex(&compressed), md5_hex(&table))), "{}", manifest);
+
+    // the digest of the manifest verifies the decompressed copy
+    let verification: VerificationReport = verify_local_files("63", folder.path().to_str().unwrap(), &options)
+        .await.unwrap();
+    assert!(verification.files.iter().all(|file| file.status == LocalFileStatus::Verified), "{:?}", verification);
+
+    let report: DownloadReport = download_record("63", &options).await.unwrap();
+    assert_eq!(report.files[0].status, FileStatus::SkippedExisting);
+    assert_eq!(zenodo.requests_for(&content_path("63", "table.csv.gz")).await.len(), 1);
+
+    fs::write(folder.path().join("table.csv"), b"changed").unwrap();
+    let report: DownloadReport = download_record("63", &options).await.unwrap();
+    assert_eq!(report.files[0].status, FileStatus::Downloaded);
+    assert_eq!(fs::read(folder.path().join("table.csv")).unwrap(), table);
+}
+
+#[tokio::test]
+async fn invalid_gzip_fails_after_its_checksum_matched()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    zenodo.record("64", &[("fake.txt.gz", b"not compressed at all")]).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).decompress(true).build().unwrap();
+
+    let report: DownloadReport = download_record("64", &options).await.unwrap();
+
+    assert_eq!(report.files[0].status, FileStatus::Failed);
+    assert!(report.files[0].error.as_deref().unwrap_or_default().contains("decompress"), "{:?}",
+        report.files[0].error);
+    assert!(!folder.path().join("fake.txt").exists());
+    assert!(!folder.path().join("fake.txt.part").exists());
+}
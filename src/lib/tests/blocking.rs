@@ -0,0 +1,61 @@
+//! The synchronous API, called from a program without an async runtime of
+//! its own.
+#![cfg(feature = "blocking")]
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::fs;
+
+use common::Zenodo;
+use zenodo_dl_core::{blocking, DownloadReport, FileStatus, LocalFileStatus, VerificationReport};
+
+
+#[test]
+fn blocking_functions_download_and_verify()
+{
+    // the mock server runs on a thread of its own once started
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let zenodo: Zenodo = runtime.block_on(async {
+        let zenodo: Zenodo = Zenodo::start().await;
+        zenodo.record("95", &[("a.txt", b"a\n"), ("b.txt", b"b\n")]).await;
+        return zenodo;
+    });
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).build().unwrap();
+
+    let report: DownloadReport = blocking::download_record("95", &options).unwrap();
+    assert!(report.files.iter().all(|file| file.status == FileStatus::Downloaded), "{:?}", report);
+    assert_eq!(fs::read(folder.path().join("b.txt")).unwrap(), b"b\n");
+
+    // the runtime of the first call serves the next ones
+    let verification: VerificationReport = blocking::verify_local_files("95", folder.path().to_str().unwrap(),
+        &options).unwrap();
+    assert!(verification.files.iter().all(|file| file.status == LocalFileStatus::Verified));
+
+    let mut out: Vec<u8> = Vec::new();
+    blocking::write_record_file("95", &["a.txt".to_string()], &mut out, &options).unwrap();
+    assert_eq!(out, b"a\n");
+}
+
+#[test]
+#[allow(deprecated)]
+fn deprecated_functions_keep_the_target_folder_as_an_argument()
+{
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let zenodo: Zenodo = runtime.block_on(async {
+        let zenodo: Zenodo = Zenodo::start().await;
+        zenodo.record("96", &[("a.txt", b"a\n")]).await;
+        return zenodo;
+    });
+    let ignored = tempfile::tempdir().unwrap();
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(ignored.path()).build().unwrap();
+
+    let report: DownloadReport = blocking::download_record_to("96", folder.path().to_str().unwrap(), &false,
+        &options).unwrap();
+
+    assert_eq!(report.files[0].status, FileStatus::Downloaded, "{:?}", report);
+    assert_eq!(fs::read(folder.path().join("a.txt")).unwrap(), b"a\n");
+    assert!(!ignored.path().join("a.txt").exists());
+}
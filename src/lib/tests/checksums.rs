@@ -0,0 +1,99 @@
+//! Verifying downloads against the checksum Zenodo reports, for every
+//! algorithm, with reference digests of a fixture file.
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+use common::{file_entry, files_body, Zenodo};
+use zenodo_dl_core::{download_record, DownloadOptions, DownloadReport, FileStatus, UnknownChecksumPolicy};
+
+
+const ALGORITHMS: [&str; 4] = ["md5", "sha1", "sha256", "sha512"];
+
+
+fn fixture(name: &str) -> PathBuf
+{
+    return PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/checksums").join(name);
+}
+
+/// The fixture file and its reference digest for `algorithm`
+fn sample(algorithm: &str) -> (Vec<u8>, String)
+{
+    let data: Vec<u8> = fs::read(fixture("sample.txt")).unwrap();
+    let digest: String = fs::read_to_string(fixture(&format!("sample.txt.{}", algorithm))).unwrap();
+    return (data, digest.trim().to_string());
+}
+
+/// Serves the fixture file as the only file of record `id`, reported with
+/// `checksum`
+async fn serve_sample(zenodo: &Zenodo, id: &str, checksum: &str)
+{
+    let (data, _) = sample("md5");
+    let mut entry: Value = file_entry(&zenodo.url(), id, "sample.txt", &data);
+    entry["checksum"] = json!(checksum);
+    zenodo.record_with(id, files_body(vec![entry])).await;
+    zenodo.content(id, "sample.txt", &data).await;
+}
+
+/// Downloads record `id` into a new folder, returning the status of its
+/// only file
+async fn download(zenodo: &Zenodo, id: &str, policy: UnknownChecksumPolicy) -> (FileStatus, TempDir)
+{
+    let folder: TempDir = tempfile::tempdir().unwrap();
+    let options: DownloadOptions = zenodo.options(folder.path()).unknown_checksum(policy).build().unwrap();
+    let report: DownloadReport = download_record(id, &options).await.unwrap();
+    return (report.files[0].status, folder);
+}
+
+
+#[tokio::test]
+async fn every_algorithm_verifies_the_fixture()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    for (index, algorithm) in ALGORITHMS.iter().enumerate() {
+        let id: String = format!("{}", 10 + index);
+        let (data, digest) = sample(algorithm);
+        serve_sample(&zenodo, &id, &format!("{}:{}", algorithm.to_uppercase(), digest)).await;
+
+        let (status, folder) = download(&zenodo, &id, UnknownChecksumPolicy::default()).await;
+        assert_eq!(status, FileStatus::Downloaded, "{}", algorithm);
+        assert_eq!(fs::read(folder.path().join("sample.txt")).unwrap(), data, "{}", algorithm);
+    }
+}
+
+#[tokio::test]
+async fn mismatching_digests_fail_for_every_algorithm()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    for (index, algorithm) in ALGORITHMS.iter().enumerate() {
+        let id: String = format!("{}", 20 + index);
+        // a digest of the right length, but of other data
+        let wrong: String = sample(algorithm).1.chars().rev().collect();
+        serve_sample(&zenodo, &id, &format!("{}:{}", algorithm, wrong)).await;
+
+        let (status, folder) = download(&zenodo, &id, UnknownChecksumPolicy::default()).await;
+        assert_eq!(status, FileStatus::ChecksumMismatch, "{}", algorithm);
+        assert!(!folder.path().join("sample.txt").exists(), "{}", algorithm);
+    }
+}
+
+#[tokio::test]
+async fn unknown_algorithms_are_skipped_or_fail_as_configured()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    serve_sample(&zenodo, "30", "crc32:cafe").await;
+
+    let (status, folder) = download(&zenodo, "30", UnknownChecksumPolicy::SkipVerification).await;
+    assert_eq!(status, FileStatus::Downloaded);
+    assert!(folder.path().join("sample.txt").exists());
+
+    let (status, folder) = download(&zenodo, "30", UnknownChecksumPolicy::Fail).await;
+    assert_eq!(status, FileStatus::Failed);
+    assert!(!folder.path().join("sample.txt").exists());
+}
@@ -0,0 +1,339 @@
+//! A Zenodo serving canned JSON and file bodies from a local mock server,
+//! shared by the tests of the library. Nothing leaves the machine.
+#![allow(clippy::needless_return)]
+#![allow(dead_code)]
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use md5::{Digest, Md5};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+use zenodo_dl_core::{ApiFlavor, DownloadOptions, DownloadOptionsBuilder, ProgressObserver, RetryPolicy, ZenodoDlError};
+
+
+/// Hex MD5 digest of `data`
+pub fn md5_hex(data: &[u8]) -> String
+{
+    return Md5::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect();
+}
+
+/// `key` as it appears in the path of its content URL
+pub fn encode_key(key: &str) -> String
+{
+    return key.bytes().map(|byte| match byte {
+        b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => (byte as char).to_string(),
+        _ => format!("%{:02X}", byte)
+    }).collect();
+}
+
+/// Path of the content of `key` in record `id`
+pub fn content_path(id: &str, key: &str) -> String
+{
+    return format!("/api/records/{}/files/{}/content", id, encode_key(key));
+}
+
+/// A file of the files endpoint of Zenodo, with its content served by
+/// `base_url`
+pub fn file_entry(base_url: &str, id: &str, key: &str, data: &[u8]) -> Value
+{
+    return json!({
+        "bucket_id": "bucket",
+        "checksum": format!("md5:{}", md5_hex(data)),
+        "created": "2024-01-01T00:00:00+00:00",
+        "file_id": format!("file-{}", key),
+        "key": key,
+        "links": {
+            "content": format!("{}{}", base_url, content_path(id, key)),
+            "self": format!("{}/api/records/{}/files/{}", base_url, id, encode_key(key)),
+        },
+        "metadata": null,
+        "mimetype": "application/octet-stream",
+        "size": data.len(),
+        "status": "completed",
+        "storage_class": "L",
+        "updated": "2024-02-03T04:05:06+00:00",
+        "version_id": "version",
+    });
+}
+
+/// Body of the files endpoint listing `entries`
+pub fn files_body(entries: Vec<Value>) -> Value
+{
+    return json!({ "enabled": true, "entries": entries });
+}
+
+/// Body of the record endpoint of record `id`
+pub fn record_body(base_url: &str, id: &str) -> Value
+{
+    return json!({
+        "id": id.parse::<u64>().unwrap_or_default(),
+        "updated": "2024-02-03T04:05:06+00:00",
+        "links": {
+            "self": format!("{}/api/records/{}", base_url, id),
+            "self_html": format!("{}/records/{}", base_url, id),
+            "latest": format!("{}/api/records/{}/versions/latest", base_url, id),
+        },
+        "versions": { "is_latest": true, "index": 1 },
+        "metadata": {
+            "title": format!("Test record {}", id),
+            "publication_date": "2024-01-01",
+            "creators": [{ "name": "Doe, Jane" }],
+            "doi": format!("10.5281/zenodo.{}", id),
+            "license": { "id": "cc-by-4.0" },
+            "version": "1.0",
+        },
+    });
+}
+
+/// `entry` with its content served from `url` instead
+pub fn served_by(mut entry: Value, url: &str) -> Value
+{
+    entry["links"]["content"] = json!(url);
+    return entry;
+}
+
+/// JSON error body as Zenodo sends it
+pub fn error_body(status: u16, message: &str) -> Value
+{
+    return json!({ "status": status, "message": message });
+}
+
+
+/// Zenodo on a local port
+pub struct Zenodo {
+    pub server: MockServer,
+}
+
+impl Zenodo {
+    pub async fn start() -> Zenodo
+    {
+        return Zenodo { server: MockServer::start().await };
+    }
+
+    pub fn url(&self) -> String
+    {
+        return self.server.uri();
+    }
+
+    /// Options downloading into `folder` from this server, retrying at once
+    /// and saving nothing but the files
+    pub fn options(&self, folder: &Path) -> DownloadOptionsBuilder
+    {
+        return DownloadOptions::builder()
+            .base_url(&self.url())
+            .api_flavor(ApiFlavor::Zenodo)
+            .target_folder(folder.to_str().expect("temporary folders have UTF-8 paths"))
+            .quiet(true)
+            .retry_policy(fast_retries(2))
+            .save_metadata(false)
+            .save_state(false)
+            .write_manifest(false);
+    }
+
+    /// Record `id` listing `files`, each served with its content
+    pub async fn record(&self, id: &str, files: &[(&str, &[u8])])
+    {
+        let entries: Vec<Value> = files.iter()
+            .map(|(key, data)| file_entry(&self.url(), id, key, data))
+            .collect();
+        self.record_with(id, files_body(entries)).await;
+        for (key, data) in files.iter() {
+            self.content(id, key, data).await;
+        }
+    }
+
+    /// Record `id` with the files endpoint answering `files`; the content
+    /// is left to the caller
+    pub async fn record_with(&self, id: &str, files: Value)
+    {
+        self.json(&format!("/api/records/{}", id), 200, record_body(&self.url(), id)).await;
+        self.json(&format!("/api/records/{}/files", id), 200, files).await;
+    }
+
+    /// Answers GET requests for `route` with `body` as JSON
+    pub async fn json(&self, route: &str, status: u16, body: Value)
+    {
+        Mock::given(method("GET")).and(path(route))
+            .respond_with(ResponseTemplate::new(status).set_body_json(body))
+            .mount(&self.server).await;
+    }
+
+    /// Serves `data` as the content of `key` in record `id`
+    pub async fn content(&self, id: &str, key: &str, data: &[u8])
+    {
+        Mock::given(method("GET")).and(path(content_path(id, key)))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(data.to_vec()))
+            .mount(&self.server).await;
+    }
+
+    /// Requests received for `route` so far
+    pub async fn requests_for(&self, route: &str) -> Vec<Request>
+    {
+        let requests: Vec<Request> = self.server.received_requests().await.unwrap_or_default();
+        return requests.into_iter().filter(|request| request.url.path() == route).collect();
+    }
+}
+
+
+/// Retries without waiting
+pub fn fast_retries(retries: u32) -> RetryPolicy
+{
+    return RetryPolicy {
+        retries,
+        base_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(1),
+    };
+}
+
+
+/// What a `RawServer` does wrong on the first request, later ones get
+/// the file or the range asked for; ranges starting at its end or beyond
+/// are always answered with 416
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// nothing, every request gets the file or the range asked for
+    Clean,
+    /// announces all of the file, then closes the connection after this
+    /// many bytes
+    Cut(usize),
+    /// announces all of the file, then sends nothing after this many bytes
+    Stall(usize),
+    /// `Cut`, and answers ranges with all of the file as if it ignored them
+    CutIgnoringRange(usize),
+    /// `Cut`, and answers ranges with a `Content-Range` starting at 0
+    CutWrongRange(usize),
+    /// always sends all of the file without `Content-Length`, closing the
+    /// connection at its end
+    NoLength,
+}
+
+/// Serves one file over plain TCP, misbehaving as no mock server can: the
+/// connection breaks or stalls in the middle of the body
+pub struct RawServer {
+    pub url: String,
+    /// `Range` headers of the requests received, `None` for none
+    ranges: Arc<Mutex<Vec<Option<String>>>>,
+}
+
+impl RawServer {
+    pub async fn start(body: Vec<u8>, fault: Fault) -> RawServer
+    {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").await.expect("cannot listen on localhost");
+        let url: String = format!("http://{}/file", listener.local_addr().expect("listener has an address"));
+        let ranges: Arc<Mutex<Vec<Option<String>>>> = Arc::new(Mutex::new(Vec::new()));
+        let seen = ranges.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(serve_raw(socket, body.clone(), fault, seen.clone()));
+            }
+        });
+        return RawServer { url, ranges };
+    }
+
+    pub fn ranges(&self) -> Vec<Option<String>>
+    {
+        return self.ranges.lock().expect("lock poisoned").clone();
+    }
+}
+
+async fn serve_raw(mut socket: TcpStream, body: Vec<u8>, fault: Fault, ranges: Arc<Mutex<Vec<Option<String>>>>)
+{
+    let mut head: Vec<u8> = Vec::new();
+    let mut byte: [u8; 1] = [0u8];
+    while !head.ends_with(b"\r\n\r\n") {
+        match socket.read(&mut byte).await {
+            Ok(1) => head.push(byte[0]),
+            _ => return
+        }
+    }
+    let range: Option<String> = String::from_utf8_lossy(&head).lines()
+        .find_map(|line| line.split_once(':')
+            .filter(|(name, _)| name.eq_ignore_ascii_case("range"))
+            .map(|(_, value)| value.trim().to_string()));
+    let first: bool = {
+        let mut ranges = ranges.lock().expect("lock poisoned");
+        ranges.push(range.clone());
+        ranges.len() == 1
+    };
+    let start: Option<usize> = range.as_deref()
+        .and_then(|range| range.strip_prefix("bytes="))
+        .and_then(|range| range.trim_end_matches('-').parse().ok());
+
+    let length: usize = body.len();
+    let whole = |sent: usize| [format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        length).into_bytes(), body[..sent].to_vec()].concat();
+    let partial = |from: usize| [format!("HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\n\
+        Content-Range: bytes {}-{}/{}\r\nConnection: close\r\n\r\n", length - start.unwrap_or_default(), from,
+        from + length - start.unwrap_or_default() - 1, length).into_bytes(),
+        body[from..from + length - start.unwrap_or_default()].to_vec()].concat();
+    let response: Vec<u8> = match (first, fault, start) {
+        (_, _, Some(start)) if start >= length => format!("HTTP/1.1 416 Range Not Satisfiable\r\n\
+            Content-Range: bytes */{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", length).into_bytes(),
+        (_, Fault::Clean, None) => whole(length),
+        (_, Fault::Clean, Some(start)) => partial(start),
+        (_, Fault::NoLength, _) => [b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n".to_vec(), body.clone()].concat(),
+        (true, Fault::Cut(sent) | Fault::Stall(sent) | Fault::CutIgnoringRange(sent) | Fault::CutWrongRange(sent), _) =>
+            whole(sent),
+        (false, Fault::CutIgnoringRange(_), _) | (false, _, None) => whole(length),
+        (false, Fault::CutWrongRange(_), Some(_)) => partial(0),
+        (false, _, Some(start)) => partial(start),
+    };
+    let _ = socket.write_all(&response).await;
+    let _ = socket.flush().await;
+    if first && matches!(fault, Fault::Stall(_)) {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    }
+}
+
+
+/// Remembers the progress reported, one line per call, e.g.
+/// `started data.bin 100`
+#[derive(Default)]
+pub struct Recorder {
+    events: Mutex<Vec<String>>,
+}
+
+impl Recorder {
+    pub fn events(&self) -> Vec<String>
+    {
+        return self.events.lock().expect("lock poisoned").clone();
+    }
+
+    fn push(&self, event: String)
+    {
+        self.events.lock().expect("lock poisoned").push(event);
+    }
+}
+
+impl ProgressObserver for Recorder {
+    fn file_started(&self, filename: &str, size: u64)
+    {
+        self.push(format!("started {} {}", filename, size));
+    }
+
+    fn file_size_known(&self, filename: &str, size: u64)
+    {
+        self.push(format!("size {} {}", filename, size));
+    }
+
+    fn file_finished(&self, filename: &str)
+    {
+        self.push(format!("finished {}", filename));
+    }
+
+    fn file_failed(&self, filename: &str, _error: &ZenodoDlError)
+    {
+        self.push(format!("failed {}", filename));
+    }
+
+    fn verification_started(&self, filename: &str, size: u64)
+    {
+        self.push(format!("verifying {} {}", filename, size));
+    }
+}
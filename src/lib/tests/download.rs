@@ -0,0 +1,553 @@
+//! Downloading records from a mock Zenodo: the happy path, the ways the
+//! server can fail and how the files of a record are picked and verified.
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use wiremock::matchers::{header_exists, method, path, query_param};
+use wiremock::{Mock, ResponseTemplate};
+
+use common::{content_path, error_body, file_entry, files_body, md5_hex, record_body, Fault, RawServer, Recorder,
+    Zenodo};
+use zenodo_dl_core::{download_file_list, download_record, get_file_list, DownloadOrder, DownloadReport,
+    DownloadSummary, FileList, FileStatus, UnsafePathPolicy, ZenodoDlError};
+
+
+#[tokio::test]
+async fn downloads_and_verifies_all_files()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = (0..50_000u32).flat_map(|index| index.to_le_bytes()).collect();
+    zenodo.record("1", &[("a.txt", b"hello world\n"), ("sub/data.bin", &data)]).await;
+    let folder = tempfile::tempdir().unwrap();
+
+    let report: DownloadReport = download_record("1", &zenodo.options(folder.path()).build().unwrap()).await.unwrap();
+
+    assert_eq!(report.record_id, "1");
+    assert!(report.files.iter().all(|file| file.status == FileStatus::Downloaded && file.error.is_none()));
+    assert_eq!(fs::read(folder.path().join("a.txt")).unwrap(), b"hello world\n");
+    assert_eq!(fs::read(folder.path().join("sub/data.bin")).unwrap(), data);
+    let summary: DownloadSummary = report.summary();
+    assert_eq!((summary.files, summary.downloaded, summary.failed), (2, 2, 0));
+    assert_eq!(summary.bytes_transferred, 12 + data.len() as u64);
+    // nothing is left behind but the files
+    assert_eq!(fs::read_dir(folder.path()).unwrap().count(), 2);
+}
+
+#[tokio::test]
+async fn accepts_record_urls_and_dois()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    zenodo.record("1", &[("a.txt", b"a\n")]).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).build().unwrap();
+
+    let url: String = format!("{}/records/1", zenodo.url());
+    assert_eq!(download_record(&url, &options).await.unwrap().record_id, "1");
+    assert_eq!(download_record("10.5281/zenodo.1", &options).await.unwrap().record_id, "1");
+}
+
+#[tokio::test]
+async fn missing_record_is_not_found()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    zenodo.json("/api/records/404", 404, error_body(404, "The persistent identifier does not exist.")).await;
+    zenodo.json("/api/records/404/files", 404, error_body(404, "The persistent identifier does not exist.")).await;
+    let folder = tempfile::tempdir().unwrap();
+
+    let err: ZenodoDlError = download_record("404", &zenodo.options(folder.path()).build().unwrap()).await
+        .unwrap_err();
+
+    match err {
+        ZenodoDlError::NotFound { message, .. } =>
+            assert_eq!(message.as_deref(), Some("The persistent identifier does not exist.")),
+        other => panic!("expected NotFound, got {:?}", other)
+    };
+    assert!(!err_is_retried(&zenodo, "/api/records/404/files").await);
+}
+
+/// Whether a request was sent more than once
+async fn err_is_retried(zenodo: &Zenodo, route: &str) -> bool
+{
+    return zenodo.requests_for(route).await.len() > 1;
+}
+
+#[tokio::test]
+async fn error_statuses_map_to_their_errors()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    for (id, status) in [("403", 403u16), ("410", 410), ("503", 503)] {
+        zenodo.json(&format!("/api/records/{}", id), status, error_body(status, "no")).await;
+        zenodo.json(&format!("/api/records/{}/files", id), status, error_body(status, "no")).await;
+    }
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).build().unwrap();
+
+    let denied: ZenodoDlError = download_record("403", &options).await.unwrap_err();
+    assert!(matches!(denied, ZenodoDlError::AccessDenied { status, .. } if status == 403), "{:?}", denied);
+    assert!(!denied.is_retryable());
+    let gone: ZenodoDlError = download_record("410", &options).await.unwrap_err();
+    assert!(matches!(gone, ZenodoDlError::Gone { .. }), "{:?}", gone);
+    let failing: ZenodoDlError = download_record("503", &options).await.unwrap_err();
+    assert!(matches!(failing, ZenodoDlError::ServerError { status, .. } if status == 503), "{:?}", failing);
+    assert!(failing.is_retryable());
+}
+
+#[tokio::test]
+async fn malformed_json_is_a_parse_error()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    zenodo.json("/api/records/1", 200, record_body(&zenodo.url(), "1")).await;
+    Mock::given(method("GET")).and(path("/api/records/1/files"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("{\"entries\": [{\"key\": ", "application/json"))
+        .mount(&zenodo.server).await;
+    let folder = tempfile::tempdir().unwrap();
+
+    let err: ZenodoDlError = download_record("1", &zenodo.options(folder.path()).build().unwrap()).await
+        .unwrap_err();
+
+    assert!(matches!(err, ZenodoDlError::JsonParse { .. }), "{:?}", err);
+    assert_eq!(fs::read_dir(folder.path()).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn checksum_mismatch_is_retried_then_reported()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let mut entry: Value = file_entry(&zenodo.url(), "1", "data.bin", b"expected");
+    entry["size"] = json!(9);
+    zenodo.record_with("1", files_body(vec![entry])).await;
+    zenodo.content("1", "data.bin", b"corrupted").await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).checksum_retries(1).build().unwrap();
+
+    let report: DownloadReport = download_record("1", &options).await.unwrap();
+
+    assert_eq!(report.files[0].status, FileStatus::ChecksumMismatch);
+    let error: &str = report.files[0].error.as_deref().unwrap();
+    assert!(error.contains(&md5_hex(b"expected")) && error.contains(&md5_hex(b"corrupted")), "{}", error);
+    assert_eq!(zenodo.requests_for(&content_path("1", "data.bin")).await.len(), 2);
+    // the corrupt download is not left where the file belongs
+    assert!(!folder.path().join("data.bin").exists());
+    assert_eq!(report.summary().failed_files, ["data.bin"]);
+}
+
+#[tokio::test]
+async fn truncated_stream_fails_without_retries()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = vec![7u8; 100_000];
+    let raw: RawServer = RawServer::start(data.clone(), Fault::Cut(30_000)).await;
+    let mut entry: Value = file_entry(&zenodo.url(), "1", "data.bin", &data);
+    entry["links"]["content"] = json!(raw.url);
+    zenodo.record_with("1", files_body(vec![entry])).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).retries(0).max_reconnects(0).build().unwrap();
+
+    let report: DownloadReport = download_record("1", &options).await.unwrap();
+
+    assert_eq!(report.files[0].status, FileStatus::Failed);
+    let error: &str = report.files[0].error.as_deref().unwrap();
+    assert!(error.starts_with("download of data.bin failed after"), "{}", error);
+    assert!(!folder.path().join("data.bin").exists());
+}
+
+#[tokio::test]
+async fn truncated_stream_is_retried()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = (0..100_000u32).map(|index| (index % 251) as u8).collect();
+    let raw: RawServer = RawServer::start(data.clone(), Fault::Cut(30_000)).await;
+    let mut entry: Value = file_entry(&zenodo.url(), "1", "data.bin", &data);
+    entry["links"]["content"] = json!(raw.url);
+    zenodo.record_with("1", files_body(vec![entry])).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).retries(1).max_reconnects(0).build().unwrap();
+
+    let report: DownloadReport = download_record("1", &options).await.unwrap();
+
+    assert_eq!(report.files[0].status, FileStatus::Downloaded);
+    assert_eq!((report.files[0].retries, report.files[0].attempts), (1, 2));
+    assert_eq!(fs::read(folder.path().join("data.bin")).unwrap(), data);
+}
+
+#[tokio::test]
+async fn rate_limit_waits_for_retry_after()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    zenodo.record("1", &[("a.txt", b"a\n")]).await;
+    Mock::given(method("GET")).and(path("/api/records/1/files"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&zenodo.server).await;
+    let folder = tempfile::tempdir().unwrap();
+
+    let started: Instant = Instant::now();
+    let report: DownloadReport = download_record("1", &zenodo.options(folder.path()).build().unwrap()).await
+        .unwrap();
+
+    assert!(started.elapsed() >= Duration::from_secs(1), "waited only {:?}", started.elapsed());
+    assert_eq!(report.files[0].status, FileStatus::Downloaded);
+    assert_eq!(zenodo.requests_for("/api/records/1/files").await.len(), 2);
+}
+
+#[tokio::test]
+async fn rate_limit_beyond_the_longest_wait_fails()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    zenodo.json("/api/records/1", 200, record_body(&zenodo.url(), "1")).await;
+    Mock::given(method("GET")).and(path("/api/records/1/files"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "120"))
+        .mount(&zenodo.server).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).max_rate_limit_wait(Duration::from_secs(10)).build().unwrap();
+
+    let started: Instant = Instant::now();
+    let err: ZenodoDlError = download_record("1", &options).await.unwrap_err();
+
+    assert!(matches!(err, ZenodoDlError::RateLimited { retry_after: Some(wait), .. } if wait == Duration::from_secs(120)),
+        "{:?}", err);
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[tokio::test]
+async fn file_lists_are_followed_across_pages()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let url: String = zenodo.url();
+    let files: Vec<(String, Vec<u8>)> = (0..7).map(|index| (format!("p{}.txt", index), format!("page {}\n", index)
+        .into_bytes())).collect();
+    let entries: Vec<Value> = files.iter().map(|(key, data)| file_entry(&url, "1", key, data)).collect();
+    zenodo.json("/api/records/1", 200, record_body(&url, "1")).await;
+    for (page, next) in [("2", json!({ "next": format!("{}/api/records/1/files?page=3&size=3", url) })),
+        ("3", json!({}))] {
+        let first: usize = (page.parse::<usize>().unwrap() - 1) * 3;
+        Mock::given(method("GET")).and(path("/api/records/1/files")).and(query_param("page", page))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "enabled": true, "entries": entries[first..std::cmp::min(first + 3, entries.len())], "links": next,
+            })))
+            .with_priority(1)
+            .mount(&zenodo.server).await;
+    }
+    zenodo.json("/api/records/1/files", 200, json!({
+        "enabled": true,
+        "entries": entries[..3],
+        "links": { "next": format!("{}/api/records/1/files?page=2&size=3", url) },
+    })).await;
+    for (key, data) in files.iter() {
+        zenodo.content("1", key, data).await;
+    }
+    let folder = tempfile::tempdir().unwrap();
+
+    let report: DownloadReport = download_record("1", &zenodo.options(folder.path()).build().unwrap()).await
+        .unwrap();
+
+    assert_eq!(report.summary().downloaded, 7);
+    for (key, data) in files.iter() {
+        assert_eq!(&fs::read(folder.path().join(key)).unwrap(), data);
+    }
+    assert_eq!(zenodo.requests_for("/api/records/1/files").await.len(), 3);
+}
+
+#[tokio::test]
+async fn legacy_record_shapes_list_their_files()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let url: String = zenodo.url();
+    // files only in the record, with `key`/`size` and `links.self`
+    let mut current: Value = record_body(&url, "10");
+    current["files"] = json!([{
+        "id": "x", "key": "old/deep.txt", "checksum": format!("md5:{}", md5_hex(b"deep\n")), "size": 5,
+        "type": "txt", "links": { "self": format!("{}/api/files/bucket/old/deep.txt", url) },
+    }]);
+    // older deposits with `filename`/`filesize`, bare digests and `links.download`
+    let mut deposit: Value = record_body(&url, "11");
+    deposit["files"] = json!([{
+        "filename": "deposit.txt", "checksum": md5_hex(b"deposit\n"), "filesize": 8,
+        "links": { "download": format!("{}/api/files/bucket/deposit.txt", url) },
+    }]);
+    zenodo.json("/api/records/10", 200, current).await;
+    zenodo.json("/api/records/10/files", 404, error_body(404, "not found")).await;
+    zenodo.json("/api/records/11", 200, deposit).await;
+    zenodo.json("/api/records/11/files", 200, json!({ "enabled": false, "entries": null })).await;
+    for (key, data) in [("old/deep.txt", &b"deep\n"[..]), ("deposit.txt", b"deposit\n")] {
+        Mock::given(method("GET")).and(path(format!("/api/files/bucket/{}", key)))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(data))
+            .mount(&zenodo.server).await;
+    }
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).build().unwrap();
+
+    assert_eq!(download_record("10", &options).await.unwrap().summary().downloaded, 1);
+    assert_eq!(download_record("11", &options).await.unwrap().summary().downloaded, 1);
+    assert_eq!(fs::read(folder.path().join("old/deep.txt")).unwrap(), b"deep\n");
+    assert_eq!(fs::read(folder.path().join("deposit.txt")).unwrap(), b"deposit\n");
+}
+
+#[tokio::test]
+async fn hostile_keys_are_left_out()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    zenodo.record("6", &[("../evil.txt", b"evil"), ("/abs.txt", b"abs"), ("a/../../up.txt", b"up"),
+        ("C:\\win.txt", b"win"), ("ok.txt", b"ok\n")]).await;
+    let parent = tempfile::tempdir().unwrap();
+    let folder = parent.path().join("target");
+    fs::create_dir(&folder).unwrap();
+
+    let report: DownloadReport = download_record("6", &zenodo.options(&folder).build().unwrap()).await.unwrap();
+
+    assert_eq!(report.files.len(), 1);
+    assert_eq!(report.files[0].filename, "ok.txt");
+    let mut written: Vec<String> = fs::read_dir(parent.path()).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    written.sort();
+    assert_eq!(written, ["target"]);
+    assert_eq!(fs::read_dir(&folder).unwrap().count(), 1);
+
+    let strict = zenodo.options(&folder).unsafe_paths(UnsafePathPolicy::Fail).build().unwrap();
+    let err: ZenodoDlError = download_record("6", &strict).await.unwrap_err();
+    assert!(matches!(err, ZenodoDlError::UnsafePath { .. }), "{:?}", err);
+}
+
+#[tokio::test]
+async fn order_decides_which_file_starts_first()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    zenodo.record("1", &[("b.txt", b"bbbbbb"), ("C.txt", b"c"), ("a.txt", b"aaa"), ("d.txt", b"ddd")]).await;
+    let started = |recorder: &Recorder| -> Vec<String> {
+        return recorder.events().iter()
+            .filter_map(|event| event.strip_prefix("started "))
+            .map(|event| event.split(' ').next().unwrap().to_string())
+            .collect();
+    };
+    for (order, expected) in [
+        (DownloadOrder::AsListed, ["b.txt", "C.txt", "a.txt", "d.txt"]),
+        // ties keep the order of the record
+        (DownloadOrder::SmallestFirst, ["C.txt", "a.txt", "d.txt", "b.txt"]),
+        (DownloadOrder::LargestFirst, ["b.txt", "a.txt", "d.txt", "C.txt"]),
+        (DownloadOrder::Alphabetical, ["a.txt", "b.txt", "C.txt", "d.txt"]),
+    ] {
+        let folder = tempfile::tempdir().unwrap();
+        let recorder: Arc<Recorder> = Arc::new(Recorder::default());
+        let options = zenodo.options(folder.path()).order(order).concurrency(1).progress(recorder.clone())
+            .build().unwrap();
+
+        let report: DownloadReport = download_record("1", &options).await.unwrap();
+
+        assert_eq!(started(&recorder), expected, "{:?}", order);
+        // the report keeps the order of the record
+        let reported: Vec<&str> = report.files.iter().map(|file| file.filename.as_str()).collect();
+        assert_eq!(reported, ["b.txt", "C.txt", "a.txt", "d.txt"]);
+    }
+}
+
+#[tokio::test]
+async fn fsync_is_recorded_per_file()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    zenodo.record("1", &[("a.txt", b"a\n"), ("b.txt", b"b\n")]).await;
+    let folder = tempfile::tempdir().unwrap();
+    fs::write(folder.path().join("a.txt"), b"a\n").unwrap();
+
+    let unsynced: DownloadReport = download_record("1", &zenodo.options(folder.path()).build().unwrap()).await
+        .unwrap();
+    assert!(unsynced.files.iter().all(|file| !file.fsynced));
+
+    fs::remove_file(folder.path().join("b.txt")).unwrap();
+    let synced: DownloadReport = download_record("1", &zenodo.options(folder.path()).fsync(true).build().unwrap())
+        .await.unwrap();
+    let fsynced: Vec<(&str, bool)> = synced.files.iter().map(|file| (file.filename.as_str(), file.fsynced)).collect();
+    // the kept file was not written
+    assert_eq!(fsynced, [("a.txt", false), ("b.txt", true)]);
+
+    // reports written before the field existed still load
+    let mut old: Value = serde_json::to_value(&synced).unwrap();
+    for file in old["files"].as_array_mut().unwrap() {
+        file.as_object_mut().unwrap().remove("fsynced");
+    }
+    let loaded: DownloadReport = serde_json::from_value(old).unwrap();
+    assert!(loaded.files.iter().all(|file| !file.fsynced));
+}
+
+#[tokio::test]
+async fn unchanged_etag_skips_hashing()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let url: String = zenodo.url();
+    zenodo.record_with("1", files_body(vec![file_entry(&url, "1", "a.txt", b"a\n")])).await;
+    Mock::given(method("GET")).and(path(content_path("1", "a.txt"))).and(header_exists("If-None-Match"))
+        .respond_with(ResponseTemplate::new(304).insert_header("ETag", "\"v1\""))
+        .with_priority(1)
+        .mount(&zenodo.server).await;
+    Mock::given(method("GET")).and(path(content_path("1", "a.txt")))
+        .respond_with(ResponseTemplate::new(200).insert_header("ETag", "\"v1\"").set_body_bytes(&b"a\n"[..]))
+        .mount(&zenodo.server).await;
+    let folder = tempfile::tempdir().unwrap();
+    let recorder: Arc<Recorder> = Arc::new(Recorder::default());
+    let options = zenodo.options(folder.path()).etags(true).progress(recorder.clone()).build().unwrap();
+
+    assert_eq!(download_record("1", &options).await.unwrap().files[0].status, FileStatus::Downloaded);
+    let report: DownloadReport = download_record("1", &options).await.unwrap();
+
+    assert_eq!(report.files[0].status, FileStatus::SkippedExisting);
+    assert_eq!(report.files[0].checked_by, Some(zenodo_dl_core::ExistingFileCheck::Etag));
+    assert!(!recorder.events().iter().any(|event| event.starts_with("verifying")), "{:?}", recorder.events());
+    let conditional: usize = zenodo.requests_for(&content_path("1", "a.txt")).await.iter()
+        .filter(|request| request.headers.get("If-None-Match").is_some_and(|etag| etag == "\"v1\""))
+        .count();
+    assert_eq!(conditional, 1);
+
+    // a local copy changed by hand is checked by its checksum instead
+    fs::write(folder.path().join("a.txt"), b"changed").unwrap();
+    let report: DownloadReport = download_record("1", &options).await.unwrap();
+    assert_eq!(report.files[0].status, FileStatus::Downloaded);
+    assert_eq!(fs::read(folder.path().join("a.txt")).unwrap(), b"a\n");
+}
+
+#[tokio::test]
+async fn cancelling_stops_a_download_in_flight()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = vec![1u8; 200_000];
+    let raw: RawServer = RawServer::start(data.clone(), Fault::Stall(50_000)).await;
+    let mut entry: Value = file_entry(&zenodo.url(), "1", "slow.bin", &data);
+    entry["links"]["content"] = json!(raw.url);
+    zenodo.record_with("1", files_body(vec![entry])).await;
+    let folder = tempfile::tempdir().unwrap();
+    let cancel = zenodo_dl_core::CancellationToken::new();
+    let options = zenodo.options(folder.path()).cancel(cancel.clone()).build().unwrap();
+
+    let canceller = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        cancel.cancel();
+    });
+    let report: DownloadReport = tokio::time::timeout(Duration::from_secs(10), download_record("1", &options)).await
+        .expect("cancelling ends the download").unwrap();
+    canceller.await.unwrap();
+
+    assert_eq!(report.files[0].status, FileStatus::Cancelled);
+    assert_eq!(report.summary().cancelled, 1);
+    assert!(!folder.path().join("slow.bin").exists());
+}
+
+#[tokio::test]
+async fn tokens_in_links_are_not_reported()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    // nothing listens on port 1, so the request fails with its URL
+    let mut entry: Value = file_entry(&zenodo.url(), "1", "shared.txt", b"shared\n");
+    entry["links"]["content"] = json!("http://127.0.0.1:1/file?token=secret-link&access_token=secret-access");
+    zenodo.record_with("1", files_body(vec![entry])).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).retries(0).token("secret-token").build().unwrap();
+
+    let report: DownloadReport = download_record("1", &options).await.unwrap();
+
+    let error: String = report.files[0].error.clone().expect("the download failed");
+    assert!(error.contains("token=***"), "{}", error);
+    assert!(!error.contains("secret"), "{}", error);
+}
+
+#[tokio::test]
+async fn write_buffer_size_does_not_change_the_bytes_written()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = (0..100_000u32).map(|index| (index % 253) as u8).collect();
+    zenodo.record("40", &[("data.bin", &data)]).await;
+
+    for write_buffer_size in [0usize, 7, 64 << 10] {
+        let folder = tempfile::tempdir().unwrap();
+        let options = zenodo.options(folder.path()).write_buffer_size(write_buffer_size).build().unwrap();
+
+        let report: DownloadReport = download_record("40", &options).await.unwrap();
+
+        assert_eq!(report.files[0].status, FileStatus::Downloaded, "{}: {:?}", write_buffer_size, report);
+        assert!(fs::read(folder.path().join("data.bin")).unwrap() == data, "{}", write_buffer_size);
+    }
+}
+
+#[tokio::test]
+async fn file_lists_survive_json_and_download_later()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    zenodo.record("37", &[("a.txt", b"a\n"), ("sub/b.txt", b"b\n")]).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).build().unwrap();
+
+    let listed: FileList = get_file_list("37", &options).await.unwrap();
+    assert_eq!(listed.record_id, "37");
+    assert_eq!(listed.files.iter().map(|file| file.filename.as_str()).collect::<Vec<_>>(), ["a.txt", "sub/b.txt"]);
+    assert!(listed.files[0].checksum.is_some() && listed.files[0].updated.is_some());
+    let mut saved: FileList = serde_json::from_str(&serde_json::to_string(&listed).unwrap()).unwrap();
+    assert_eq!(saved, listed);
+
+    // the local names come from the keys, not from the listing
+    saved.files[0].local_name = "../escaped.txt".to_string();
+    let report: DownloadReport = download_file_list(&saved, &options).await.unwrap();
+
+    assert!(report.files.iter().all(|file| file.status == FileStatus::Downloaded), "{:?}", report);
+    assert_eq!(fs::read(folder.path().join("a.txt")).unwrap(), b"a\n");
+    assert_eq!(fs::read(folder.path().join("sub/b.txt")).unwrap(), b"b\n");
+    assert!(!folder.path().parent().unwrap().join("escaped.txt").exists());
+}
+
+#[tokio::test]
+async fn file_lists_send_the_token_to_the_api_server_only()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    zenodo.record("37", &[("a.txt", b"a\n")]).await;
+    let elsewhere: Zenodo = Zenodo::start().await;
+    elsewhere.content("37", "a.txt", b"forged\n").await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).token("secret").build().unwrap();
+
+    let listed: FileList = get_file_list("37", &options).await.unwrap();
+    let mut saved: FileList = serde_json::from_str(&serde_json::to_string(&listed).unwrap()).unwrap();
+    saved.files[0].url = saved.files[0].url.replace(&zenodo.url(), &elsewhere.url());
+    let report: DownloadReport = download_file_list(&saved, &options).await.unwrap();
+
+    assert_eq!(report.files[0].status, FileStatus::Downloaded, "{:?}", report);
+    assert_eq!(fs::read(folder.path().join("a.txt")).unwrap(), b"a\n");
+    assert!(elsewhere.server.received_requests().await.unwrap().is_empty());
+    let requests = zenodo.requests_for(&content_path("37", "a.txt")).await;
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].headers.get("authorization").unwrap(), "Bearer secret");
+}
+
+#[tokio::test]
+async fn pages_on_other_servers_are_fetched_without_the_token()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let url: String = zenodo.url();
+    let elsewhere: Zenodo = Zenodo::start().await;
+    let entries: Vec<Value> = [("a.txt", &b"a\n"[..]), ("b.txt", b"b\n")].iter()
+        .map(|(key, data)| file_entry(&url, "38", key, data))
+        .collect();
+    zenodo.json("/api/records/38", 200, record_body(&url, "38")).await;
+    zenodo.json("/api/records/38/files", 200, json!({
+        "enabled": true,
+        "entries": entries[..1],
+        "links": { "next": format!("{}/api/records/38/files?page=2", elsewhere.url()) },
+    })).await;
+    elsewhere.json("/api/records/38/files", 200, json!({ "enabled": true, "entries": entries[1..] })).await;
+    zenodo.content("38", "a.txt", b"a\n").await;
+    zenodo.content("38", "b.txt", b"b\n").await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).token("secret").build().unwrap();
+
+    let report: DownloadReport = download_record("38", &options).await.unwrap();
+
+    assert_eq!(report.summary().downloaded, 2, "{:?}", report);
+    let listed = zenodo.requests_for("/api/records/38/files").await;
+    assert_eq!(listed[0].headers.get("authorization").unwrap(), "Bearer secret");
+    let followed = elsewhere.requests_for("/api/records/38/files").await;
+    assert_eq!(followed.len(), 1);
+    assert!(followed[0].headers.get("authorization").is_none());
+}
@@ -0,0 +1,137 @@
+//! Hashing local files: the digests for every way of reading them, and
+//! hashing that must not hold up the downloads running at the same time.
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::{md5_hex, Zenodo};
+use zenodo_dl_core::{download_record, file_digest, verify_local_files, ChecksumAlgorithm, DownloadReport,
+    FileStatus, HashReading, LocalFileStatus, ProgressObserver, VerificationReport, DEFAULT_HASH_CHUNK_SIZE};
+
+
+/// Every way of reading a file for hashing, one byte at a time included
+fn readings() -> Vec<HashReading>
+{
+    return vec![
+        HashReading::Chunked(1),
+        HashReading::Chunked(0),
+        HashReading::Chunked(1000),
+        HashReading::default(),
+        #[cfg(feature = "mmap")]
+        HashReading::Mapped,
+    ];
+}
+
+
+#[test]
+fn digests_match_the_reference_values()
+{
+    let folder = tempfile::tempdir().unwrap();
+    let abc = folder.path().join("abc");
+    let empty = folder.path().join("empty");
+    fs::write(&abc, b"abc").unwrap();
+    fs::write(&empty, b"").unwrap();
+    let expected = [
+        (ChecksumAlgorithm::Md5, "900150983cd24fb0d6963f7d28e17f72", "d41d8cd98f00b204e9800998ecf8427e"),
+        (ChecksumAlgorithm::Sha1, "a9993e364706816aba3e25717850c26c9cd0d89d",
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709"),
+        (ChecksumAlgorithm::Sha256, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+        (ChecksumAlgorithm::Sha512,
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+            2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",
+            "cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce\
+            47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e"),
+    ];
+
+    for reading in readings() {
+        for (algorithm, abc_digest, empty_digest) in expected.iter() {
+            assert_eq!(file_digest(&abc, *algorithm, reading).unwrap(), *abc_digest, "{:?} {:?}", algorithm, reading);
+            assert_eq!(file_digest(&empty, *algorithm, reading).unwrap(), *empty_digest, "{:?} {:?}", algorithm,
+                reading);
+        }
+    }
+}
+
+#[test]
+fn files_beyond_a_chunk_are_hashed_whole()
+{
+    let folder = tempfile::tempdir().unwrap();
+    let path = folder.path().join("large");
+    // one byte more than a chunk, so that the last read is a short one
+    let data: Vec<u8> = (0..DEFAULT_HASH_CHUNK_SIZE + 1).map(|index| (index % 241) as u8).collect();
+    fs::write(&path, &data).unwrap();
+
+    for reading in readings().into_iter().filter(|reading| *reading != HashReading::Chunked(1)) {
+        assert_eq!(file_digest(&path, ChecksumAlgorithm::Md5, reading).unwrap(), md5_hex(&data), "{:?}", reading);
+    }
+    assert!(file_digest(&folder.path().join("missing"), ChecksumAlgorithm::Md5, HashReading::default()).is_err());
+}
+
+#[tokio::test]
+async fn local_files_are_verified_as_the_options_read_them()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    zenodo.record("80", &[("a.txt", b"some text\n")]).await;
+    let folder = tempfile::tempdir().unwrap();
+    fs::write(folder.path().join("a.txt"), b"some text\n").unwrap();
+
+    for reading in readings() {
+        let options = zenodo.options(folder.path()).hash_reading(reading).build().unwrap();
+        let report: VerificationReport = verify_local_files("80", folder.path().to_str().unwrap(), &options).await
+            .unwrap();
+        assert_eq!(report.files[0].status, LocalFileStatus::Verified, "{:?}", reading);
+    }
+}
+
+
+/// Takes its time over every step of hashing a file, as a slow terminal
+/// or GUI might
+#[derive(Default)]
+struct SlowObserver {
+    events: Mutex<Vec<String>>,
+}
+
+impl ProgressObserver for SlowObserver {
+    fn file_finished(&self, filename: &str)
+    {
+        self.events.lock().unwrap().push(format!("finished {}", filename));
+    }
+
+    fn verification_advanced(&self, _filename: &str, _bytes: u64)
+    {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    fn verification_finished(&self, filename: &str)
+    {
+        self.events.lock().unwrap().push(format!("verified {}", filename));
+    }
+}
+
+#[tokio::test]
+async fn hashing_an_existing_file_does_not_hold_up_other_downloads()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let existing: Vec<u8> = vec![3u8; 1 << 20];
+    zenodo.record("81", &[("existing.bin", &existing), ("small.txt", b"small\n")]).await;
+    let folder = tempfile::tempdir().unwrap();
+    fs::write(folder.path().join("existing.bin"), &existing).unwrap();
+    let observer: Arc<SlowObserver> = Arc::new(SlowObserver::default());
+    // 64 steps of 20ms; the test runs on a single thread, so the download
+    // only gets ahead if the hashing runs elsewhere
+    let options = zenodo.options(folder.path()).concurrency(2).hash_reading(HashReading::Chunked(16 * 1024))
+        .progress(observer.clone()).build().unwrap();
+
+    let report: DownloadReport = download_record("81", &options).await.unwrap();
+
+    assert_eq!(report.files[0].status, FileStatus::SkippedExisting);
+    assert_eq!(report.files[1].status, FileStatus::Downloaded);
+    let events: Vec<String> = observer.events.lock().unwrap().clone();
+    let position = |event: &str| events.iter().position(|other| other == event);
+    assert!(position("finished small.txt") < position("verified existing.bin"), "{:?}", events);
+}
@@ -0,0 +1,125 @@
+//! What a download leaves in the target folder: files that no longer belong
+//! to the record and the state used to retry the files that failed.
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+
+use common::{content_path, file_entry, files_body, Zenodo};
+use zenodo_dl_core::{download_failed_files, download_record, find_stale_files, remove_stale_files, DownloadReport,
+    FileStatus, StaleFiles, ZenodoDlError, STATE_FILENAME};
+
+
+fn write_files(folder: &Path, files: &[&str])
+{
+    for file in files.iter() {
+        let path = folder.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, file.as_bytes()).unwrap();
+    }
+}
+
+
+#[tokio::test]
+async fn files_not_in_the_record_are_listed_and_removed()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    zenodo.record("50", &[("a.txt", b"a"), ("c.txt", b"c"), ("sub/b.txt", b"b")]).await;
+    let folder = tempfile::tempdir().unwrap();
+    let target: &str = folder.path().to_str().unwrap();
+    // partial downloads, manifests and hidden files are written by downloads
+    write_files(folder.path(), &["a.txt", "c.txt", "sub/b.txt", "old.txt", "gone/x.txt", "c.txt.part", "MD5SUMS",
+        ".hidden"]);
+    let options = zenodo.options(folder.path()).build().unwrap();
+
+    let stale: StaleFiles = find_stale_files("50", target, &options).await.unwrap();
+
+    assert_eq!(stale.record_id, "50");
+    assert_eq!(stale.files, vec!["gone/x.txt", "old.txt"]);
+    assert_eq!(stale.matching, 3);
+
+    remove_stale_files(target, &stale, &options).unwrap();
+
+    assert!(!folder.path().join("old.txt").exists());
+    assert!(!folder.path().join("gone").exists());
+    for kept in ["a.txt", "c.txt", "sub/b.txt", "c.txt.part", "MD5SUMS", ".hidden"] {
+        assert!(folder.path().join(kept).exists(), "{} was removed", kept);
+    }
+}
+
+#[tokio::test]
+async fn folder_of_another_record_is_not_cleaned_up()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    zenodo.record("51", &[("a.txt", b"a")]).await;
+    let folder = tempfile::tempdir().unwrap();
+    write_files(folder.path(), &["a.txt", "thesis.tex", "notes.md", "figure.png"]);
+    let options = zenodo.options(folder.path()).build().unwrap();
+
+    let err: ZenodoDlError = find_stale_files("51", folder.path().to_str().unwrap(), &options).await.unwrap_err();
+
+    match err {
+        ZenodoDlError::UnrelatedFolder { matching, files, .. } => assert_eq!((matching, files), (1, 4)),
+        other => panic!("expected UnrelatedFolder, got {:?}", other)
+    };
+    assert!(folder.path().join("thesis.tex").exists());
+}
+
+#[tokio::test]
+async fn only_failed_files_are_retried()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    // the content of b.txt is missing at first
+    zenodo.record_with("52", files_body(vec![
+        file_entry(&zenodo.url(), "52", "a.txt", b"a\n"),
+        file_entry(&zenodo.url(), "52", "b.txt", b"b\n"),
+    ])).await;
+    zenodo.content("52", "a.txt", b"a\n").await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).save_state(true).retries(0).build().unwrap();
+
+    let report: DownloadReport = download_record("52", &options).await.unwrap();
+    assert_eq!(report.files[1].status, FileStatus::Failed);
+
+    zenodo.content("52", "b.txt", b"b\n").await;
+    let report: DownloadReport = download_failed_files(&options).await.unwrap();
+
+    assert_eq!(report.record_id, "52");
+    assert_eq!(report.files.len(), 1);
+    assert_eq!(report.files[0].filename, "b.txt");
+    assert_eq!(report.files[0].status, FileStatus::Downloaded);
+    assert_eq!(fs::read(folder.path().join("b.txt")).unwrap(), b"b\n");
+    assert_eq!(zenodo.requests_for(&content_path("52", "a.txt")).await.len(), 1);
+
+    // the state holds the results of both runs, so nothing is left to retry
+    let state: Value = serde_json::from_slice(&fs::read(folder.path().join(STATE_FILENAME)).unwrap()).unwrap();
+    let statuses: Vec<&str> = state["report"]["files"].as_array().unwrap().iter()
+        .map(|file| file["status"].as_str().unwrap())
+        .collect();
+    assert_eq!(statuses, vec!["downloaded", "downloaded"]);
+    let report: DownloadReport = download_failed_files(&options).await.unwrap();
+    assert!(report.files.is_empty());
+}
+
+#[tokio::test]
+async fn retrying_needs_a_readable_state()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).save_state(true).build().unwrap();
+
+    let err: ZenodoDlError = download_failed_files(&options).await.unwrap_err();
+    assert!(matches!(err, ZenodoDlError::NoState { .. }), "{:?}", err);
+
+    for content in ["{ not json", r#"{"version": 99, "report": {}}"#, r#"{"report": {}}"#] {
+        fs::write(folder.path().join(STATE_FILENAME), content).unwrap();
+        let err: ZenodoDlError = download_failed_files(&options).await.unwrap_err();
+        assert!(matches!(err, ZenodoDlError::InvalidState { .. }), "{}: {:?}", content, err);
+    }
+    // nothing was downloaded instead
+    assert!(zenodo.server.received_requests().await.unwrap().is_empty());
+}
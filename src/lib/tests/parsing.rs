@@ -0,0 +1,117 @@
+//! Parsing what users type or servers send: checksums, record identifiers
+//! and sizes.
+#![allow(clippy::needless_return)]
+
+use zenodo_dl_core::{parse_identifier, parse_size, Checksum, ChecksumAlgorithm, RecordIdentifier, ZenodoDlError};
+
+
+#[test]
+fn checksums_are_parsed_with_or_without_their_algorithm()
+{
+    let md5: Checksum = Checksum::parse("md5:abc").unwrap();
+    assert_eq!((md5.algorithm.as_str(), md5.value.as_str()), ("md5", "abc"));
+    assert_eq!(md5.known_algorithm(), Some(ChecksumAlgorithm::Md5));
+
+    let digest: String = "AB".repeat(32);
+    let sha256: Checksum = Checksum::parse(&format!(" SHA256:{} ", digest)).unwrap();
+    assert_eq!((sha256.algorithm.as_str(), sha256.value.as_str()), ("sha256", digest.to_lowercase().as_str()));
+    assert_eq!(sha256.known_algorithm(), Some(ChecksumAlgorithm::Sha256));
+
+    // without a prefix only the length tells the algorithm
+    assert_eq!(Checksum::parse(&"0".repeat(32)).unwrap().algorithm, "md5");
+    assert_eq!(Checksum::parse(&"0".repeat(64)).unwrap().algorithm, "sha256");
+    assert_eq!(Checksum::parse("abc"), None);
+}
+
+#[test]
+fn empty_and_malformed_checksums_are_none()
+{
+    for raw in ["", "  ", "md5:", "md5: ", ":abc", "md5:xyz"] {
+        assert_eq!(Checksum::parse(raw), None, "{:?}", raw);
+    }
+}
+
+#[test]
+fn checksums_of_unknown_algorithms_are_kept_but_cannot_be_verified()
+{
+    let checksum: Checksum = Checksum::parse("crc32:cafe").unwrap();
+    assert_eq!((checksum.algorithm.as_str(), checksum.value.as_str()), ("crc32", "cafe"));
+    assert_eq!(checksum.known_algorithm(), None);
+}
+
+#[test]
+fn zenodo_dois_are_record_ids_in_every_spelling()
+{
+    for raw in ["10.5281/zenodo.1234", "doi:10.5281/zenodo.1234", "DOI:10.5281/ZENODO.1234",
+        "https://doi.org/10.5281/zenodo.1234", "http://dx.doi.org/10.5281/zenodo.1234", " 1234 "] {
+        let expected: RecordIdentifier = RecordIdentifier::RecordId("1234".to_string());
+        assert_eq!(parse_identifier(raw, "https://zenodo.org").unwrap(), expected, "{:?}", raw);
+    }
+}
+
+#[test]
+fn other_dois_are_resolved_later()
+{
+    for raw in ["10.1000/xyz.42", "doi:10.1000/xyz.42", "https://doi.org/10.1000/xyz.42"] {
+        let expected: RecordIdentifier = RecordIdentifier::Doi("10.1000/xyz.42".to_string());
+        assert_eq!(parse_identifier(raw, "https://zenodo.org").unwrap(), expected, "{:?}", raw);
+    }
+}
+
+#[test]
+fn record_urls_of_zenodo_and_the_base_url_are_accepted()
+{
+    for (raw, base_url) in [("https://zenodo.org/records/1234#files", "https://zenodo.org"),
+        ("https://www.zenodo.org/record/1234", "https://zenodo.org"),
+        ("https://zenodo.org/uploads/1234", "https://zenodo.org"),
+        ("https://data.example.org/records/a1b2c-3d4e5?preview=1", "https://data.example.org")] {
+        let expected: &str = if raw.contains("example") { "a1b2c-3d4e5" } else { "1234" };
+        assert_eq!(parse_identifier(raw, base_url).unwrap(), RecordIdentifier::RecordId(expected.to_string()),
+            "{:?}", raw);
+    }
+}
+
+#[test]
+fn other_identifiers_are_rejected()
+{
+    for raw in ["", "doi:", "https://doi.org/", "https://example.org/records/1234", "https://zenodo.org/search",
+        "zenodo record"] {
+        let err: ZenodoDlError = parse_identifier(raw, "https://zenodo.org").unwrap_err();
+        assert!(matches!(err, ZenodoDlError::InvalidIdentifier(_)), "{:?}: {:?}", raw, err);
+    }
+}
+
+#[test]
+fn sizes_are_decimal_without_i_and_binary_with_it()
+{
+    let sizes: [(&str, u64); 16] = [
+        ("0", 0),
+        ("500", 500),
+        ("500B", 500),
+        ("1k", 1_000),
+        ("1K", 1_000),
+        ("1KB", 1_000),
+        ("1Ki", 1_024),
+        ("1KiB", 1_024),
+        ("500M", 500_000_000),
+        ("500Mi", 500 << 20),
+        ("1.5G", 1_500_000_000),
+        ("2GiB", 2 << 30),
+        ("2 gib", 2 << 30),
+        ("3T", 3_000_000_000_000),
+        ("3Ti", 3 << 40),
+        (" 1.5Ki ", 1_536),
+    ];
+    for (raw, expected) in sizes {
+        assert_eq!(parse_size(raw).unwrap(), expected, "{:?}", raw);
+    }
+}
+
+#[test]
+fn invalid_sizes_are_rejected()
+{
+    for raw in ["", " ", "M", "5X", "5iB", "1.2.3M", "-5M", "99999999999999999999", "20000000P"] {
+        let err: ZenodoDlError = parse_size(raw).unwrap_err();
+        assert!(matches!(err, ZenodoDlError::InvalidSize(_)), "{:?}: {:?}", raw, err);
+    }
+}
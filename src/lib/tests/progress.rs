@@ -0,0 +1,166 @@
+//! Progress as JSON lines, the layout of the events and how often they are
+//! written, and as events sent to a channel nobody may be reading.
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::json;
+use tokio::sync::mpsc;
+
+use common::{file_entry, files_body, Zenodo};
+use zenodo_dl_core::{download_record, DownloadEvent, DownloadReport, FileStatus, JsonLinesProgress, ProgressEvent,
+    ProgressObserver};
+
+
+/// Collects what is written to it, readable while the progress holds it
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn events(&self) -> Vec<ProgressEvent>
+    {
+        let buffer = self.0.lock().unwrap();
+        return String::from_utf8_lossy(&buffer).lines()
+            .map(|line| serde_json::from_str(line).unwrap_or_else(|err| panic!("{}: {}", line, err)))
+            .collect();
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize>
+    {
+        self.0.lock().unwrap().extend_from_slice(data);
+        return Ok(data.len());
+    }
+
+    fn flush(&mut self) -> io::Result<()>
+    {
+        return Ok(());
+    }
+}
+
+fn progress(name: &str, bytes: u64, total: u64) -> ProgressEvent
+{
+    return ProgressEvent::Progress { name: name.to_string(), bytes, total };
+}
+
+
+#[test]
+fn events_have_a_stable_layout()
+{
+    let events = [
+        (ProgressEvent::DownloadStart { files: 2 }, json!({ "event": "download_start", "files": 2 })),
+        (ProgressEvent::FileStart { name: "a.csv".to_string(), size: 10 },
+            json!({ "event": "file_start", "name": "a.csv", "size": 10 })),
+        (progress("a.csv", 5, 10), json!({ "event": "progress", "name": "a.csv", "bytes": 5, "total": 10 })),
+        (ProgressEvent::FileDone { name: "a.csv".to_string() }, json!({ "event": "file_done", "name": "a.csv" })),
+        (ProgressEvent::FileFailed { name: "b.csv".to_string(), reason: "gone".to_string() },
+            json!({ "event": "file_failed", "name": "b.csv", "reason": "gone" })),
+        (ProgressEvent::DownloadDone, json!({ "event": "download_done" })),
+    ];
+
+    for (event, expected) in events.into_iter() {
+        assert_eq!(serde_json::to_value(&event).unwrap(), expected);
+        assert_eq!(serde_json::from_value::<ProgressEvent>(expected).unwrap(), event);
+    }
+}
+
+#[test]
+fn progress_is_throttled_but_the_end_of_a_file_is_not()
+{
+    let buffer: SharedBuffer = SharedBuffer::default();
+    let observer: JsonLinesProgress = JsonLinesProgress::with_writer(Box::new(buffer.clone()));
+
+    observer.file_started("a.csv", 100);
+    for bytes in [10, 20, 30, 100] {
+        observer.bytes_advanced("a.csv", bytes);
+    }
+    observer.file_finished("a.csv");
+
+    assert_eq!(buffer.events(), vec![
+        ProgressEvent::FileStart { name: "a.csv".to_string(), size: 100 },
+        progress("a.csv", 10, 100),
+        progress("a.csv", 100, 100),
+        ProgressEvent::FileDone { name: "a.csv".to_string() },
+    ]);
+}
+
+#[test]
+fn total_is_zero_until_the_size_is_known()
+{
+    let buffer: SharedBuffer = SharedBuffer::default();
+    let observer: JsonLinesProgress = JsonLinesProgress::with_writer(Box::new(buffer.clone()));
+
+    observer.file_started("b.bin", 0);
+    observer.bytes_advanced("b.bin", 5);
+    observer.file_size_known("b.bin", 50);
+    observer.bytes_advanced("b.bin", 50);
+
+    assert_eq!(buffer.events()[1..], [progress("b.bin", 5, 0), progress("b.bin", 50, 50)]);
+}
+
+#[tokio::test]
+async fn download_reports_each_file_between_start_and_end()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    // the content of missing.txt is not served
+    zenodo.record_with("90", files_body(vec![file_entry(&zenodo.url(), "90", "a.txt", b"a\n"),
+        file_entry(&zenodo.url(), "90", "missing.txt", b"missing\n")])).await;
+    zenodo.content("90", "a.txt", b"a\n").await;
+    let folder = tempfile::tempdir().unwrap();
+    let buffer: SharedBuffer = SharedBuffer::default();
+    let options = zenodo.options(folder.path()).retries(0).concurrency(1)
+        .progress(Arc::new(JsonLinesProgress::with_writer(Box::new(buffer.clone())))).build().unwrap();
+
+    download_record("90", &options).await.unwrap();
+
+    let events: Vec<ProgressEvent> = buffer.events();
+    assert_eq!(events.first(), Some(&ProgressEvent::DownloadStart { files: 2 }));
+    assert_eq!(events.last(), Some(&ProgressEvent::DownloadDone));
+    assert!(events.contains(&ProgressEvent::FileStart { name: "a.txt".to_string(), size: 2 }));
+    assert!(events.contains(&progress("a.txt", 2, 2)));
+    assert!(events.contains(&ProgressEvent::FileDone { name: "a.txt".to_string() }));
+    assert!(events.iter().any(|event| matches!(event, ProgressEvent::FileFailed { name, .. } if name == "missing.txt")),
+        "{:?}", events);
+}
+
+#[tokio::test]
+async fn a_consumer_not_reading_events_does_not_hold_up_the_download()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = (0..1_000_000u32).flat_map(|index| index.to_le_bytes()).collect();
+    zenodo.record("91", &[("a.bin", &data), ("b.txt", b"b\n")]).await;
+    let folder = tempfile::tempdir().unwrap();
+    let (sender, mut receiver) = mpsc::channel::<DownloadEvent>(1);
+    let options = zenodo.options(folder.path()).concurrency(1).events(sender).build().unwrap();
+
+    // the channel is full after the first event and nothing is received
+    // until the download is over
+    let report: DownloadReport = tokio::time::timeout(Duration::from_secs(10), download_record("91", &options)).await
+        .expect("a full channel does not stall the download").unwrap();
+    assert!(report.files.iter().all(|file| file.status == FileStatus::Downloaded), "{:?}", report);
+
+    let mut events: Vec<DownloadEvent> = Vec::new();
+    while let Ok(Some(event)) = tokio::time::timeout(Duration::from_secs(5), receiver.recv()).await {
+        let finished: bool = event == DownloadEvent::RecordFinished;
+        events.push(event);
+        if finished {
+            break;
+        }
+    }
+    // all arrives in order, the progress of each file held back as one
+    assert_eq!(events, vec![
+        DownloadEvent::RecordStarted { files: 2 },
+        DownloadEvent::FileStarted { name: "a.bin".to_string(), size: data.len() as u64 },
+        DownloadEvent::Progress { name: "a.bin".to_string(), bytes: data.len() as u64, total: data.len() as u64 },
+        DownloadEvent::FileFinished { name: "a.bin".to_string() },
+        DownloadEvent::FileStarted { name: "b.txt".to_string(), size: 2 },
+        DownloadEvent::Progress { name: "b.txt".to_string(), bytes: 2, total: 2 },
+        DownloadEvent::FileFinished { name: "b.txt".to_string() },
+        DownloadEvent::RecordFinished,
+    ]);
+}
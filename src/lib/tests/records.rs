@@ -0,0 +1,331 @@
+//! Records that are not simply a list of complete files: embargoed,
+//! restricted and draft records, files still uploading, of unknown size or
+//! without links, and records of other InvenioRDM instances.
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use wiremock::matchers::{header, header_exists, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use common::{content_path, error_body, file_entry, files_body, md5_hex, record_body, Fault, RawServer, Recorder,
+    Zenodo};
+use zenodo_dl_core::{download_record, get_record_metadata, list_record_files, plan_record_download, verify_local_files, ApiFlavor,
+    DownloadOptions, DownloadReport, ExistingFileCheck, FileInfo, FileStatus, LocalFileStatus, PlannedAction,
+    RecordMetadata, VerificationReport, ZenodoDlError};
+
+
+#[tokio::test]
+async fn embargoed_files_name_the_end_of_the_embargo()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let mut record: Value = record_body(&zenodo.url(), "18");
+    record["access"] = json!({
+        "record": "public",
+        "files": "restricted",
+        "embargo": { "active": true, "until": "2026-01-01", "reason": null },
+        "status": "embargoed",
+    });
+    zenodo.json("/api/records/18", 200, record).await;
+    zenodo.json("/api/records/18/files", 403, error_body(403, "Permission denied.")).await;
+    let folder = tempfile::tempdir().unwrap();
+
+    for save_metadata in [false, true] {
+        let options = zenodo.options(folder.path()).save_metadata(save_metadata).build().unwrap();
+        let err: ZenodoDlError = download_record("18", &options).await.unwrap_err();
+        match err {
+            ZenodoDlError::Embargoed { record_id, until } => {
+                assert_eq!(record_id, "18");
+                assert_eq!(until.as_deref(), Some("2026-01-01"));
+            },
+            other => panic!("expected Embargoed, got {:?}", other)
+        };
+    }
+}
+
+#[tokio::test]
+async fn record_metadata_tells_the_files_and_their_access()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let mut listed: Value = record_body(&zenodo.url(), "25");
+    listed["files"] = json!([file_entry(&zenodo.url(), "25", "a.txt", b"a\n"),
+        file_entry(&zenodo.url(), "25", "b.txt", b"bb\n")]);
+    zenodo.json("/api/records/25", 200, listed).await;
+    let mut metadata_only: Value = record_body(&zenodo.url(), "26");
+    metadata_only["files"] = json!({ "enabled": false });
+    zenodo.json("/api/records/26", 200, metadata_only).await;
+    let mut embargoed: Value = record_body(&zenodo.url(), "27");
+    embargoed["access"] = json!({ "files": "restricted", "embargo": { "active": true, "until": "2030-01-01" } });
+    zenodo.json("/api/records/27", 200, embargoed).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).follow_latest(false).build().unwrap();
+
+    let listed: RecordMetadata = get_record_metadata("25", &options).await.unwrap();
+    assert_eq!(listed.listed_files(), Some((2, 5)));
+    assert!(listed.access_error().is_none());
+    let metadata_only: RecordMetadata = get_record_metadata("26", &options).await.unwrap();
+    assert_eq!(metadata_only.listed_files(), Some((0, 0)));
+    let embargoed: RecordMetadata = get_record_metadata("27", &options).await.unwrap();
+    assert_eq!(embargoed.listed_files(), None);
+    assert!(matches!(embargoed.access_error(), Some(ZenodoDlError::Embargoed { .. })), "{:?}", embargoed.raw);
+    // nothing but the records themselves was asked for
+    assert!(zenodo.server.received_requests().await.unwrap().iter()
+        .all(|request| !request.url.path().ends_with("/files")));
+}
+
+#[tokio::test]
+async fn restricted_files_point_to_the_record_page()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let mut record: Value = record_body(&zenodo.url(), "19");
+    record["metadata"]["access_right"] = json!("restricted");
+    zenodo.json("/api/records/19", 200, record).await;
+    zenodo.json("/api/records/19/files", 200, json!({ "enabled": false, "entries": null })).await;
+    // records of metadata only have no files to download at all
+    zenodo.json("/api/records/20", 200, record_body(&zenodo.url(), "20")).await;
+    zenodo.json("/api/records/20/files", 200, json!({ "enabled": false, "entries": null })).await;
+    let folder = tempfile::tempdir().unwrap();
+
+    for save_metadata in [false, true] {
+        let options = zenodo.options(folder.path()).save_metadata(save_metadata).build().unwrap();
+        let err: ZenodoDlError = download_record("19", &options).await.unwrap_err();
+        match err {
+            ZenodoDlError::Restricted { record_id, record_url } => {
+                assert_eq!(record_id, "19");
+                assert_eq!(record_url, Some(format!("{}/records/19", zenodo.url())));
+            },
+            other => panic!("expected Restricted, got {:?}", other)
+        };
+        let err: ZenodoDlError = download_record("20", &options).await.unwrap_err();
+        assert!(matches!(err, ZenodoDlError::NoFiles(ref id) if id == "20"), "{:?}", err);
+    }
+}
+
+#[tokio::test]
+async fn files_still_uploading_are_skipped()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let url: String = zenodo.url();
+    let mut pending: Value = file_entry(&url, "21", "pending.bin", b"pppp");
+    pending["status"] = json!("pending");
+    let mut aborted: Value = file_entry(&url, "21", "aborted.bin", b"aaaa");
+    aborted["status"] = json!("aborted");
+    zenodo.record_with("21", files_body(vec![file_entry(&url, "21", "done.txt", b"done\n"), pending, aborted]))
+        .await;
+    zenodo.content("21", "done.txt", b"done\n").await;
+    for key in ["pending.bin", "aborted.bin"] {
+        zenodo.json(&content_path("21", key), 404, error_body(404, "not found")).await;
+    }
+    let folder = tempfile::tempdir().unwrap();
+    let target: &str = folder.path().to_str().unwrap();
+    let options = zenodo.options(folder.path()).build().unwrap();
+
+    let plan = plan_record_download("21", target, &options).await.unwrap();
+    let actions: Vec<PlannedAction> = plan.files.iter().map(|file| file.action).collect();
+    assert_eq!(actions, [PlannedAction::Download, PlannedAction::SkipIncomplete, PlannedAction::SkipIncomplete]);
+
+    let report: DownloadReport = download_record("21", &options).await.unwrap();
+    let statuses: Vec<FileStatus> = report.files.iter().map(|file| file.status).collect();
+    assert_eq!(statuses, [FileStatus::Downloaded, FileStatus::SkippedIncomplete, FileStatus::SkippedIncomplete]);
+    assert_eq!((report.summary().incomplete, report.summary().failed), (2, 0));
+    assert!(zenodo.requests_for(&content_path("21", "pending.bin")).await.is_empty());
+
+    // nothing can be missing of what was never there
+    let verification: VerificationReport = verify_local_files("21", target, &options).await.unwrap();
+    assert_eq!(verification.files.len(), 1);
+    assert!(verification.is_complete());
+}
+
+#[tokio::test]
+async fn files_of_unknown_size_take_the_size_of_the_response()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let url: String = zenodo.url();
+    let data: Vec<u8> = b"no size\n".repeat(1000);
+    let mut missing: Value = file_entry(&url, "22", "nosize.txt", &data);
+    missing.as_object_mut().unwrap().remove("size");
+    let mut zero: Value = file_entry(&url, "22", "zero.bin", &data);
+    zero["size"] = json!(0);
+    let mut null: Value = file_entry(&url, "22", "null.bin", &data);
+    null["size"] = Value::Null;
+    zenodo.record_with("22", files_body(vec![missing, zero, null])).await;
+    for key in ["nosize.txt", "zero.bin", "null.bin"] {
+        zenodo.content("22", key, &data).await;
+    }
+    let folder = tempfile::tempdir().unwrap();
+    let recorder: Arc<Recorder> = Arc::new(Recorder::default());
+    let options = zenodo.options(folder.path()).progress(recorder.clone()).build().unwrap();
+
+    let report: DownloadReport = download_record("22", &options).await.unwrap();
+
+    assert_eq!(report.summary().downloaded, 3, "{:?}", report);
+    let events: Vec<String> = recorder.events();
+    for key in ["nosize.txt", "zero.bin", "null.bin"] {
+        assert!(events.contains(&format!("started {} 0", key)), "{:?}", events);
+        assert!(events.contains(&format!("size {} {}", key, data.len())), "{:?}", events);
+        assert_eq!(fs::read(folder.path().join(key)).unwrap(), data);
+    }
+
+    // without a size, checking it means checking the file exists
+    fs::write(folder.path().join("zero.bin"), b"anything").unwrap();
+    let by_size = zenodo.options(folder.path()).verify_existing(ExistingFileCheck::Size).build().unwrap();
+    let report: DownloadReport = download_record("22", &by_size).await.unwrap();
+    assert!(report.files.iter().all(|file| file.status == FileStatus::SkippedExisting));
+    assert!(report.files.iter().all(|file| file.checked_by == Some(ExistingFileCheck::Existence)), "{:?}", report);
+}
+
+#[tokio::test]
+async fn files_of_unknown_size_without_content_length_are_decided_by_checksum()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = (0..40_000u32).map(|index| (index % 199) as u8).collect();
+    let raw: RawServer = RawServer::start(data.clone(), Fault::NoLength).await;
+    let mut unknown: Value = file_entry(&zenodo.url(), "22", "stream.bin", &data);
+    unknown["size"] = json!(0);
+    unknown["links"]["content"] = json!(raw.url);
+    let mut corrupt: Value = unknown.clone();
+    corrupt["key"] = json!("corrupt.bin");
+    corrupt["checksum"] = json!(format!("md5:{}", md5_hex(b"something else")));
+    zenodo.record_with("22", files_body(vec![unknown, corrupt])).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).retries(0).checksum_retries(0).build().unwrap();
+
+    let report: DownloadReport = download_record("22", &options).await.unwrap();
+
+    assert_eq!(report.files[0].status, FileStatus::Downloaded, "{:?}", report.files[0].error);
+    assert_eq!(fs::read(folder.path().join("stream.bin")).unwrap(), data);
+    assert_eq!(report.files[1].status, FileStatus::ChecksumMismatch, "{:?}", report.files[1].error);
+}
+
+#[tokio::test]
+async fn files_without_links_are_fetched_from_their_content_url()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let url: String = zenodo.url();
+    let files: [(&str, &[u8]); 3] = [("with space.txt", b"space\n"), ("a+b.txt", b"plus\n"),
+        ("\u{fc}n\u{ef}c\u{f8}d\u{e9}.txt", b"unicode\n")];
+    let mut entries: Vec<Value> = files.iter()
+        .map(|(key, data)| {
+            let mut entry: Value = file_entry(&url, "23", key, data);
+            entry.as_object_mut().unwrap().remove("links");
+            entry
+        })
+        .collect();
+    // Zenodo's self link is the content itself
+    let mut self_only: Value = file_entry(&url, "23", "dir/selfonly.txt", b"self\n");
+    self_only["links"] = json!({ "self": format!("{}/api/files/bucket/dir/selfonly.txt", url) });
+    entries.push(self_only);
+    zenodo.record_with("23", files_body(entries)).await;
+    for (key, data) in files.iter() {
+        zenodo.content("23", key, data).await;
+    }
+    Mock::given(method("GET")).and(path("/api/files/bucket/dir/selfonly.txt"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(&b"self\n"[..]))
+        .mount(&zenodo.server).await;
+    let folder = tempfile::tempdir().unwrap();
+
+    let report: DownloadReport = download_record("23", &zenodo.options(folder.path()).build().unwrap()).await
+        .unwrap();
+
+    assert_eq!(report.summary().downloaded, 4, "{:?}", report);
+    for path in ["/api/records/23/files/with%20space.txt/content", "/api/records/23/files/a%2Bb.txt/content",
+        "/api/records/23/files/%C3%BCn%C3%AFc%C3%B8d%C3%A9.txt/content"] {
+        assert_eq!(zenodo.requests_for(path).await.len(), 1, "{}", path);
+    }
+    assert_eq!(fs::read(folder.path().join("dir/selfonly.txt")).unwrap(), b"self\n");
+}
+
+#[tokio::test]
+async fn drafts_are_downloaded_with_the_token_of_their_owner()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let url: String = zenodo.url();
+    let owner = || header("Authorization", "Bearer owner");
+    let mut record: Value = record_body(&url, "24");
+    record["metadata"]["title"] = json!("My draft");
+    let mut uploading: Value = file_entry(&url, "24", "uploading.bin", b"uuuuu");
+    uploading["status"] = json!("pending");
+    uploading.as_object_mut().unwrap().remove("checksum");
+    let mut ready: Value = file_entry(&url, "24", "draft ok.txt", b"draft\n");
+    ready["links"] = json!({ "self": format!("{}/api/records/24/draft/files/draft%20ok.txt", url) });
+    for (route, body) in [("/api/records/24/draft", record), ("/api/records/24/draft/files",
+        files_body(vec![ready, uploading]))] {
+        Mock::given(method("GET")).and(path(route)).and(owner())
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .mount(&zenodo.server).await;
+    }
+    Mock::given(method("GET")).and(path("/api/records/24/draft/files/draft%20ok.txt/content")).and(owner())
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(&b"draft\n"[..]))
+        .mount(&zenodo.server).await;
+    // other tokens are refused, requests without one find nothing
+    Mock::given(method("GET")).and(header_exists("Authorization"))
+        .respond_with(ResponseTemplate::new(403).set_body_json(error_body(403, "Permission denied.")))
+        .with_priority(10)
+        .mount(&zenodo.server).await;
+    let folder = tempfile::tempdir().unwrap();
+    let draft = |token: Option<&str>| -> DownloadOptions {
+        let builder = zenodo.options(folder.path()).draft(true).follow_latest(true);
+        return match token {
+            Some(token) => builder.token(token),
+            None => builder
+        }.build().unwrap();
+    };
+
+    let report: DownloadReport = download_record(&format!("{}/uploads/24", url), &draft(Some("owner"))).await
+        .unwrap();
+    let statuses: Vec<FileStatus> = report.files.iter().map(|file| file.status).collect();
+    assert_eq!(statuses, [FileStatus::Downloaded, FileStatus::SkippedIncomplete]);
+    assert_eq!(fs::read(folder.path().join("draft ok.txt")).unwrap(), b"draft\n");
+    let requests = zenodo.server.received_requests().await.unwrap();
+    assert!(requests.iter().all(|request| request.url.path().starts_with("/api/records/24/draft")),
+        "{:?}", requests.iter().map(|request| request.url.path()).collect::<Vec<&str>>());
+
+    let err: ZenodoDlError = download_record("24", &draft(None)).await.unwrap_err();
+    assert!(matches!(err, ZenodoDlError::DraftNotAccessible { with_token: false, .. }), "{:?}", err);
+    let err: ZenodoDlError = download_record("24", &draft(Some("someone else"))).await.unwrap_err();
+    assert!(matches!(err, ZenodoDlError::DraftNotAccessible { with_token: true, .. }), "{:?}", err);
+}
+
+/// The fixtures of `tests/fixtures/invenio_rdm`, with their links pointing
+/// to `base_url`
+fn invenio_fixture(name: &str, base_url: &str) -> Value
+{
+    let path: PathBuf = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/invenio_rdm").join(name);
+    let content: String = fs::read_to_string(path).unwrap().replace("https://data.caltech.edu", base_url);
+    return serde_json::from_str(&content).unwrap();
+}
+
+#[tokio::test]
+async fn invenio_rdm_records_are_downloaded()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let url: String = zenodo.url();
+    zenodo.json("/api/records/a1b2c-3d4e5", 200, invenio_fixture("record.json", &url)).await;
+    zenodo.json("/api/records/a1b2c-3d4e5/files", 200, invenio_fixture("files.json", &url)).await;
+    let content: PathBuf = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/invenio_rdm/content");
+    for key in ["readings.csv", "README.md"] {
+        zenodo.content("a1b2c-3d4e5", key, &fs::read(content.join(key)).unwrap()).await;
+    }
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).api_flavor(ApiFlavor::InvenioRdm).build().unwrap();
+
+    let listed: Vec<FileInfo> = list_record_files("a1b2c-3d4e5", &options).await.unwrap();
+    let urls: Vec<&str> = listed.iter().map(|file| file.url.as_str()).collect();
+    // README.md only links its metadata, with the content below
+    assert_eq!(urls, [format!("{}/api/records/a1b2c-3d4e5/files/readings.csv/content", url),
+        format!("{}/api/records/a1b2c-3d4e5/files/README.md/content", url)]);
+    let report: DownloadReport = download_record("a1b2c-3d4e5", &options).await.unwrap();
+
+    assert_eq!(report.summary().downloaded, 2, "{:?}", report);
+    for key in ["readings.csv", "README.md"] {
+        assert_eq!(fs::read(folder.path().join(key)).unwrap(), fs::read(content.join(key)).unwrap());
+    }
+    let verification: VerificationReport = verify_local_files("a1b2c-3d4e5", folder.path().to_str().unwrap(),
+        &options).await.unwrap();
+    assert_eq!(verification.count(LocalFileStatus::Verified), 2);
+}
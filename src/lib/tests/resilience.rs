@@ -0,0 +1,406 @@
+//! Downloads that break off: connections cut or stalled in the middle of a
+//! file, corrupt partial files and runs interrupted before they finished.
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use common::{content_path, fast_retries, file_entry, files_body, record_body, served_by, Fault, RawServer, Recorder,
+    Zenodo};
+use zenodo_dl_core::{download_record, resume_download, CancellationToken, DownloadReport, FileResult, FileStatus,
+    STATE_FILENAME};
+
+
+/// 40 kB that are not all the same, so that misplaced bytes show
+fn test_data() -> Vec<u8>
+{
+    return (0..40_000u32).map(|index| (index % 251) as u8).collect();
+}
+
+/// Record `id` with the one file `data.bin` served by `raw`
+async fn raw_record(zenodo: &Zenodo, id: &str, data: &[u8], raw: &RawServer)
+{
+    let entry: Value = served_by(file_entry(&zenodo.url(), id, "data.bin", data), &raw.url);
+    zenodo.record_with(id, files_body(vec![entry])).await;
+}
+
+fn assert_downloaded(result: &FileResult, folder: &std::path::Path, data: &[u8])
+{
+    assert_eq!(result.status, FileStatus::Downloaded, "{:?}", result.error);
+    assert_eq!(fs::read(folder.join(&result.filename)).unwrap(), data);
+}
+
+
+#[tokio::test]
+async fn stalled_download_reconnects_where_it_stopped()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = test_data();
+    let raw: RawServer = RawServer::start(data.clone(), Fault::Stall(10_000)).await;
+    raw_record(&zenodo, "30", &data, &raw).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).stall_timeout(Duration::from_millis(300)).build().unwrap();
+
+    let report: DownloadReport = download_record("30", &options).await.unwrap();
+
+    assert_downloaded(&report.files[0], folder.path(), &data);
+    assert_eq!(report.files[0].reconnects, 1);
+    assert_eq!(report.files[0].retries, 0);
+    assert_eq!(raw.ranges(), vec![None, Some("bytes=10000-".to_string())]);
+}
+
+#[tokio::test]
+async fn stalled_download_without_reconnects_is_retried_from_its_part_file()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = test_data();
+    let raw: RawServer = RawServer::start(data.clone(), Fault::Stall(10_000)).await;
+    raw_record(&zenodo, "30", &data, &raw).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).stall_timeout(Duration::from_millis(300)).max_reconnects(0)
+        .build().unwrap();
+
+    let report: DownloadReport = download_record("30", &options).await.unwrap();
+
+    assert_downloaded(&report.files[0], folder.path(), &data);
+    assert_eq!(report.files[0].reconnects, 0);
+    assert_eq!(report.files[0].retries, 1);
+    assert_eq!(raw.ranges(), vec![None, Some("bytes=10000-".to_string())]);
+}
+
+#[tokio::test]
+async fn zero_stall_timeout_waits_for_the_server()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = test_data();
+    let raw: RawServer = RawServer::start(data.clone(), Fault::Stall(10_000)).await;
+    raw_record(&zenodo, "30", &data, &raw).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).stall_timeout(Duration::ZERO).build().unwrap();
+
+    let waited = tokio::time::timeout(Duration::from_secs(2), download_record("30", &options)).await;
+
+    assert!(waited.is_err(), "the download gave up on the stalled server");
+    assert_eq!(raw.ranges(), vec![None]);
+}
+
+#[tokio::test]
+async fn stalled_file_does_not_hold_up_the_others()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = test_data();
+    let raw: RawServer = RawServer::start(data.clone(), Fault::Stall(10_000)).await;
+    let entries: Vec<Value> = vec![
+        served_by(file_entry(&zenodo.url(), "30", "data.bin", &data), &raw.url),
+        file_entry(&zenodo.url(), "30", "healthy.txt", b"healthy\n"),
+    ];
+    zenodo.record_with("30", files_body(entries)).await;
+    zenodo.content("30", "healthy.txt", b"healthy\n").await;
+    let folder = tempfile::tempdir().unwrap();
+    let recorder: Arc<Recorder> = Arc::new(Recorder::default());
+    let options = zenodo.options(folder.path()).stall_timeout(Duration::from_millis(500)).concurrency(2)
+        .progress(recorder.clone()).build().unwrap();
+
+    let report: DownloadReport = download_record("30", &options).await.unwrap();
+
+    assert_downloaded(&report.files[0], folder.path(), &data);
+    assert_downloaded(&report.files[1], folder.path(), b"healthy\n");
+    let finished: Vec<String> = recorder.events().into_iter().filter(|event| event.starts_with("finished")).collect();
+    assert_eq!(finished, vec!["finished healthy.txt", "finished data.bin"]);
+}
+
+#[tokio::test]
+async fn cut_connection_is_continued_with_a_range_request()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = test_data();
+    let raw: RawServer = RawServer::start(data.clone(), Fault::Cut(10_000)).await;
+    raw_record(&zenodo, "31", &data, &raw).await;
+    let folder = tempfile::tempdir().unwrap();
+
+    let report: DownloadReport = download_record("31", &zenodo.options(folder.path()).build().unwrap()).await
+        .unwrap();
+
+    assert_downloaded(&report.files[0], folder.path(), &data);
+    assert_eq!(report.files[0].reconnects, 1);
+    assert_eq!(report.files[0].retries, 0);
+    assert_eq!(report.files[0].attempts, 1);
+    assert_eq!(raw.ranges(), vec![None, Some("bytes=10000-".to_string())]);
+}
+
+#[tokio::test]
+async fn cut_connection_without_reconnects_is_retried()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = test_data();
+    let raw: RawServer = RawServer::start(data.clone(), Fault::Cut(10_000)).await;
+    raw_record(&zenodo, "31", &data, &raw).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).max_reconnects(0).build().unwrap();
+
+    let report: DownloadReport = download_record("31", &options).await.unwrap();
+
+    assert_downloaded(&report.files[0], folder.path(), &data);
+    assert_eq!(report.files[0].reconnects, 0);
+    assert_eq!(report.files[0].retries, 1);
+    assert_eq!(report.files[0].attempts, 2);
+}
+
+#[tokio::test]
+async fn servers_not_continuing_at_the_cut_fall_back_to_a_retry()
+{
+    for fault in [Fault::CutIgnoringRange(10_000), Fault::CutWrongRange(10_000)] {
+        let zenodo: Zenodo = Zenodo::start().await;
+        let data: Vec<u8> = test_data();
+        let raw: RawServer = RawServer::start(data.clone(), fault).await;
+        raw_record(&zenodo, "31", &data, &raw).await;
+        let folder = tempfile::tempdir().unwrap();
+        let options = zenodo.options(folder.path()).retry_policy(fast_retries(3)).checksum_retries(2)
+            .build().unwrap();
+
+        let report: DownloadReport = download_record("31", &options).await.unwrap();
+
+        assert_downloaded(&report.files[0], folder.path(), &data);
+        assert_eq!(report.files[0].reconnects, 0, "{:?}", fault);
+        assert!(report.files[0].retries >= 1, "{:?}", fault);
+        assert_eq!(raw.ranges()[1].as_deref(), Some("bytes=10000-"), "{:?}", fault);
+    }
+}
+
+#[tokio::test]
+async fn corrupt_partial_file_is_downloaded_again()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = test_data();
+    let raw: RawServer = RawServer::start(data.clone(), Fault::Clean).await;
+    raw_record(&zenodo, "32", &data, &raw).await;
+    let folder = tempfile::tempdir().unwrap();
+    fs::write(folder.path().join("data.bin.part"), vec![0xffu8; 10_000]).unwrap();
+    let options = zenodo.options(folder.path()).checksum_retries(0).build().unwrap();
+
+    let report: DownloadReport = download_record("32", &options).await.unwrap();
+
+    assert_downloaded(&report.files[0], folder.path(), &data);
+    assert_eq!(report.files[0].retries, 1);
+    assert_eq!(raw.ranges(), vec![Some("bytes=10000-".to_string()), None]);
+    assert!(!folder.path().join("data.bin.part").exists());
+}
+
+#[tokio::test]
+async fn intact_partial_file_is_resumed()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = test_data();
+    let raw: RawServer = RawServer::start(data.clone(), Fault::Clean).await;
+    raw_record(&zenodo, "32", &data, &raw).await;
+    let folder = tempfile::tempdir().unwrap();
+    fs::write(folder.path().join("data.bin.part"), &data[..10_000]).unwrap();
+
+    let report: DownloadReport = download_record("32", &zenodo.options(folder.path()).build().unwrap()).await
+        .unwrap();
+
+    assert_downloaded(&report.files[0], folder.path(), &data);
+    assert_eq!(report.files[0].retries, 0);
+    assert_eq!(report.files[0].bytes_transferred, 30_000);
+    assert_eq!(raw.ranges(), vec![Some("bytes=10000-".to_string())]);
+}
+
+#[tokio::test]
+async fn complete_partial_file_is_verified_without_a_request()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = test_data();
+    let raw: RawServer = RawServer::start(data.clone(), Fault::Clean).await;
+    raw_record(&zenodo, "38", &data, &raw).await;
+    let folder = tempfile::tempdir().unwrap();
+    fs::write(folder.path().join("data.bin.part"), &data).unwrap();
+    let options = zenodo.options(folder.path()).keep_partial(true).build().unwrap();
+
+    let report: DownloadReport = download_record("38", &options).await.unwrap();
+
+    assert_downloaded(&report.files[0], folder.path(), &data);
+    assert_eq!(report.files[0].bytes_transferred, 0);
+    assert!(raw.ranges().is_empty(), "{:?}", raw.ranges());
+    assert!(!folder.path().join("data.bin.part").exists());
+}
+
+#[tokio::test]
+async fn complete_but_corrupt_partial_file_is_downloaded_again()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = test_data();
+    let raw: RawServer = RawServer::start(data.clone(), Fault::Clean).await;
+    raw_record(&zenodo, "38", &data, &raw).await;
+    let folder = tempfile::tempdir().unwrap();
+    fs::write(folder.path().join("data.bin.part"), vec![0xffu8; data.len()]).unwrap();
+
+    let report: DownloadReport = download_record("38", &zenodo.options(folder.path()).build().unwrap()).await
+        .unwrap();
+
+    assert_downloaded(&report.files[0], folder.path(), &data);
+    assert_eq!(report.files[0].retries, 0);
+    assert_eq!(raw.ranges(), vec![None]);
+}
+
+#[tokio::test]
+async fn unsatisfiable_range_restarts_the_download()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = test_data();
+    let raw: RawServer = RawServer::start(data.clone(), Fault::Clean).await;
+    // without a size in the listing only the server can tell the .part
+    // file is complete
+    let mut entry: Value = served_by(file_entry(&zenodo.url(), "39", "data.bin", &data), &raw.url);
+    entry["size"] = json!(0);
+    zenodo.record_with("39", files_body(vec![entry])).await;
+    let folder = tempfile::tempdir().unwrap();
+    fs::write(folder.path().join("data.bin.part"), &data).unwrap();
+
+    let report: DownloadReport = download_record("39", &zenodo.options(folder.path()).build().unwrap()).await
+        .unwrap();
+
+    assert_downloaded(&report.files[0], folder.path(), &data);
+    assert_eq!(report.files[0].retries, 0);
+    assert_eq!(raw.ranges(), vec![Some("bytes=40000-".to_string()), None]);
+}
+
+/// Starts downloading record `id` and cancels it once it had time to
+/// receive what the stalling server sends
+async fn cancelled_download(id: &str, options: zenodo_dl_core::DownloadOptionsBuilder) -> DownloadReport
+{
+    let cancel: CancellationToken = CancellationToken::new();
+    let options = options.cancel(cancel.clone()).build().unwrap();
+    let canceller = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        cancel.cancel();
+    });
+    let report: DownloadReport = download_record(id, &options).await.unwrap();
+    canceller.await.unwrap();
+    return report;
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn part_file_reserves_its_size_but_resumes_from_its_length()
+{
+    use std::os::unix::fs::MetadataExt;
+
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = vec![7u8; 1 << 20];
+    let raw: RawServer = RawServer::start(data.clone(), Fault::Stall(10_000)).await;
+    raw_record(&zenodo, "33", &data, &raw).await;
+    let folder = tempfile::tempdir().unwrap();
+
+    let report: DownloadReport = cancelled_download("33", zenodo.options(folder.path())).await;
+
+    assert_eq!(report.files[0].status, FileStatus::Cancelled);
+    let part = fs::metadata(folder.path().join("data.bin.part")).unwrap();
+    assert_eq!(part.len(), 10_000);
+    assert!(part.blocks() * 512 >= data.len() as u64, "only {} blocks reserved", part.blocks());
+
+    let report: DownloadReport = download_record("33", &zenodo.options(folder.path()).build().unwrap()).await
+        .unwrap();
+
+    assert_downloaded(&report.files[0], folder.path(), &data);
+    assert_eq!(raw.ranges().last().unwrap().as_deref(), Some("bytes=10000-"));
+}
+
+#[tokio::test]
+async fn interrupted_download_is_resumed_from_its_state()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = test_data();
+    let raw: RawServer = RawServer::start(data.clone(), Fault::Stall(10_000)).await;
+    let entries: Vec<Value> = vec![
+        file_entry(&zenodo.url(), "34", "first.txt", b"first\n"),
+        served_by(file_entry(&zenodo.url(), "34", "data.bin", &data), &raw.url),
+    ];
+    zenodo.record_with("34", files_body(entries)).await;
+    zenodo.content("34", "first.txt", b"first\n").await;
+    let folder = tempfile::tempdir().unwrap();
+
+    // the record is fetched for its metadata, which tells when it was updated
+    let report: DownloadReport = cancelled_download("34", zenodo.options(folder.path()).save_state(true)
+        .save_metadata(true).concurrency(1)).await;
+    assert_eq!(report.files[0].status, FileStatus::Downloaded);
+    assert_eq!(report.files[1].status, FileStatus::Cancelled);
+
+    let options = zenodo.options(folder.path()).save_state(true).build().unwrap();
+    let report: DownloadReport = resume_download(&options).await.unwrap();
+
+    assert_eq!(report.record_id, "34");
+    assert_eq!(report.files[0].status, FileStatus::SkippedExisting);
+    assert_downloaded(&report.files[1], folder.path(), &data);
+    assert_eq!(raw.ranges().last().unwrap().as_deref(), Some("bytes=10000-"));
+    // the saved file list was used as the record was not updated
+    assert_eq!(zenodo.requests_for("/api/records/34/files").await.len(), 1);
+    assert_eq!(zenodo.requests_for(&content_path("34", "first.txt")).await.len(), 1);
+}
+
+#[tokio::test]
+async fn updated_record_is_listed_again_when_resuming()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    zenodo.record("35", &[("a.txt", b"old\n"), ("b.txt", b"b\n")]).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).save_state(true).save_metadata(true).build().unwrap();
+    download_record("35", &options).await.unwrap();
+
+    zenodo.server.reset().await;
+    let mut record: Value = record_body(&zenodo.url(), "35");
+    record["updated"] = json!("2025-01-01T00:00:00+00:00");
+    zenodo.json("/api/records/35", 200, record).await;
+    zenodo.json("/api/records/35/files", 200, files_body(vec![
+        file_entry(&zenodo.url(), "35", "a.txt", b"new\n"),
+        file_entry(&zenodo.url(), "35", "b.txt", b"b\n"),
+    ])).await;
+    zenodo.content("35", "a.txt", b"new\n").await;
+    zenodo.content("35", "b.txt", b"b\n").await;
+
+    let report: DownloadReport = resume_download(&options).await.unwrap();
+
+    assert_downloaded(&report.files[0], folder.path(), b"new\n");
+    assert_eq!(report.files[1].status, FileStatus::SkippedExisting);
+    assert_eq!(zenodo.requests_for("/api/records/35/files").await.len(), 1);
+}
+
+#[tokio::test]
+async fn state_of_the_first_layout_is_resumed()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    zenodo.record("36", &[("a.txt", b"a\n"), ("b.txt", b"b\n")]).await;
+    let folder = tempfile::tempdir().unwrap();
+    fs::write(folder.path().join("a.txt"), b"a\n").unwrap();
+    // version 1 only held the report of the last run
+    let state: Value = json!({
+        "version": 1,
+        "report": {
+            "record_id": "36",
+            "files": [{
+                "filename": "a.txt",
+                "size": 2,
+                "status": "downloaded",
+                "bytes_transferred": 2,
+                "elapsed_secs": 0.1,
+                "retries": 0,
+                "error": null,
+            }],
+            "elapsed_secs": 0.1,
+        },
+    });
+    fs::write(folder.path().join(STATE_FILENAME), state.to_string()).unwrap();
+    let options = zenodo.options(folder.path()).save_state(true).build().unwrap();
+
+    let report: DownloadReport = resume_download(&options).await.unwrap();
+
+    assert_eq!(report.files.len(), 2);
+    assert_downloaded(&report.files[1], folder.path(), b"b\n");
+    let saved: Value = serde_json::from_slice(&fs::read(folder.path().join(STATE_FILENAME)).unwrap()).unwrap();
+    assert_eq!(saved["version"], 2);
+    assert_eq!(saved["files"].as_array().map(|files| files.len()), Some(2));
+}
@@ -0,0 +1,126 @@
+//! Writing a single file of a record to a stream, as `-o -` does.
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::time::Duration;
+
+use serde_json::Value;
+
+use common::{content_path, file_entry, files_body, served_by, Fault, RawServer, Zenodo};
+use zenodo_dl_core::{write_record_file, ZenodoDlError};
+
+
+fn test_data() -> Vec<u8>
+{
+    return (0..40_000u32).map(|index| (index % 253) as u8).collect();
+}
+
+/// Record `id` with the one file `data.bin` served by `raw`
+async fn raw_record(zenodo: &Zenodo, id: &str, data: &[u8], raw: &RawServer)
+{
+    let entry: Value = served_by(file_entry(&zenodo.url(), id, "data.bin", data), &raw.url);
+    zenodo.record_with(id, files_body(vec![entry])).await;
+}
+
+
+#[tokio::test]
+async fn single_file_is_written_to_the_stream()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    zenodo.record("70", &[("a.txt", b"hello\n"), ("b.txt", b"world\n")]).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).build().unwrap();
+    let mut out: Vec<u8> = Vec::new();
+
+    let written: u64 = write_record_file("70", &["b.txt".to_string()], &mut out, &options).await.unwrap();
+
+    assert_eq!(written, 6);
+    assert_eq!(out, b"world\n");
+    // nothing lands in the target folder
+    assert_eq!(std::fs::read_dir(folder.path()).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn exactly_one_file_has_to_be_selected()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    zenodo.record("71", &[("results.csv", b"1,2\n"), ("readme.txt", b"read me\n")]).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).build().unwrap();
+    let mut out: Vec<u8> = Vec::new();
+
+    let err: ZenodoDlError = write_record_file("71", &[], &mut out, &options).await.unwrap_err();
+    assert!(matches!(err, ZenodoDlError::NotSingleFile { selected: 2, .. }), "{:?}", err);
+
+    let names: Vec<String> = vec!["result.csv".to_string()];
+    match write_record_file("71", &names, &mut out, &options).await.unwrap_err() {
+        ZenodoDlError::FileNotInRecord { filename, suggestions, .. } => {
+            assert_eq!(filename, "result.csv");
+            assert_eq!(suggestions, vec!["results.csv"]);
+        },
+        other => panic!("expected FileNotInRecord, got {:?}", other)
+    };
+
+    let names: Vec<String> = vec!["results.csv".to_string(), "readme.txt".to_string()];
+    let err: ZenodoDlError = write_record_file("71", &names, &mut out, &options).await.unwrap_err();
+    assert!(matches!(err, ZenodoDlError::NotSingleFile { selected: 2, .. }), "{:?}", err);
+    assert!(out.is_empty());
+}
+
+#[tokio::test]
+async fn interrupted_stream_continues_where_it_stopped()
+{
+    for fault in [Fault::Cut(10_000), Fault::Stall(10_000)] {
+        let zenodo: Zenodo = Zenodo::start().await;
+        let data: Vec<u8> = test_data();
+        let raw: RawServer = RawServer::start(data.clone(), fault).await;
+        raw_record(&zenodo, "72", &data, &raw).await;
+        let folder = tempfile::tempdir().unwrap();
+        let options = zenodo.options(folder.path()).stall_timeout(Duration::from_millis(300)).build().unwrap();
+        let mut out: Vec<u8> = Vec::new();
+
+        let written: u64 = write_record_file("72", &[], &mut out, &options).await.unwrap();
+
+        assert_eq!(written, data.len() as u64, "{:?}", fault);
+        assert!(out == data, "{:?}: the stream is not the file", fault);
+        assert_eq!(raw.ranges(), vec![None, Some("bytes=10000-".to_string())], "{:?}", fault);
+    }
+}
+
+#[tokio::test]
+async fn server_starting_over_fails_the_stream()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    let data: Vec<u8> = test_data();
+    let raw: RawServer = RawServer::start(data.clone(), Fault::CutIgnoringRange(10_000)).await;
+    raw_record(&zenodo, "73", &data, &raw).await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).build().unwrap();
+    let mut out: Vec<u8> = Vec::new();
+
+    let err: ZenodoDlError = write_record_file("73", &[], &mut out, &options).await.unwrap_err();
+
+    assert!(matches!(err, ZenodoDlError::HttpStatus { .. }), "{:?}", err);
+    // the bytes written are not repeated
+    assert!(out == data[..10_000], "the stream is not the start of the file");
+}
+
+#[tokio::test]
+async fn checksum_mismatch_is_reported_after_writing()
+{
+    let zenodo: Zenodo = Zenodo::start().await;
+    // the record lists other bytes than the server sends
+    zenodo.record_with("74", files_body(vec![file_entry(&zenodo.url(), "74", "data.bin", b"expected")])).await;
+    zenodo.content("74", "data.bin", b"received").await;
+    let folder = tempfile::tempdir().unwrap();
+    let options = zenodo.options(folder.path()).build().unwrap();
+    let mut out: Vec<u8> = Vec::new();
+
+    let err: ZenodoDlError = write_record_file("74", &[], &mut out, &options).await.unwrap_err();
+
+    assert!(matches!(err, ZenodoDlError::ChecksumMismatch { .. }), "{:?}", err);
+    assert_eq!(out, b"received");
+    // written bytes cannot be taken back, so nothing is repeated
+    assert_eq!(zenodo.requests_for(&content_path("74", "data.bin")).await.len(), 1);
+}
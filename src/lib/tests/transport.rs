@@ -0,0 +1,130 @@
+//! Downloads through a transport injected into the options, served from
+//! memory without any server.
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+use reqwest::StatusCode;
+
+use common::{fast_retries, file_entry, files_body, record_body};
+use zenodo_dl_core::{download_record, get_record_metadata, ApiFlavor, DownloadOptions, DownloadReport, FileStatus,
+    HttpRequest, HttpResponse, HttpTransport, TransportError, TransportFuture, ZenodoDlError};
+
+
+const BASE_URL: &str = "https://zenodo.test";
+
+/// Answers with the bodies of known URLs and 404 otherwise, noting the
+/// method and the authorization of each request
+#[derive(Default)]
+struct MemoryTransport {
+    bodies: HashMap<String, Vec<u8>>,
+    requests: Mutex<Vec<(&'static str, String, Option<String>)>>,
+}
+
+impl MemoryTransport {
+    fn answer(&self, method: &'static str, request: HttpRequest) -> TransportFuture<'_>
+    {
+        let authorization: Option<String> = request.header_value(&reqwest::header::AUTHORIZATION)
+            .map(|value| value.to_string());
+        self.requests.lock().unwrap().push((method, request.url.clone(), authorization));
+        let response: HttpResponse = match self.bodies.get(&request.url) {
+            Some(body) => HttpResponse::new(StatusCode::OK, body.clone()),
+            None => HttpResponse::new(StatusCode::NOT_FOUND, Vec::new())
+        };
+        return Box::pin(async move { Ok(response) });
+    }
+}
+
+impl HttpTransport for MemoryTransport {
+    fn get_metadata(&self, request: HttpRequest) -> TransportFuture<'_>
+    {
+        return self.answer("metadata", request);
+    }
+
+    fn stream_file(&self, request: HttpRequest) -> TransportFuture<'_>
+    {
+        return self.answer("file", request);
+    }
+}
+
+/// Fails every request as timed out
+struct TimeoutTransport;
+
+impl HttpTransport for TimeoutTransport {
+    fn get_metadata(&self, _request: HttpRequest) -> TransportFuture<'_>
+    {
+        return Box::pin(async { Err(TransportError::Timeout("no answer".to_string())) });
+    }
+
+    fn stream_file(&self, request: HttpRequest) -> TransportFuture<'_>
+    {
+        return self.get_metadata(request);
+    }
+}
+
+
+#[tokio::test]
+async fn download_record_runs_on_an_injected_transport()
+{
+    let record_url: String = format!("{}/api/records/30", BASE_URL);
+    let entries = vec![file_entry(BASE_URL, "30", "a.txt", b"a\n"), file_entry(BASE_URL, "30", "b.txt", b"b\n")];
+    let mut bodies: HashMap<String, Vec<u8>> = HashMap::new();
+    bodies.insert(record_url.clone(), record_body(BASE_URL, "30").to_string().into_bytes());
+    bodies.insert(record_url.clone() + "/files", files_body(entries.clone()).to_string().into_bytes());
+    for (entry, content) in entries.iter().zip([b"a\n", b"b\n"]) {
+        bodies.insert(entry["links"]["content"].as_str().unwrap().to_string(), content.to_vec());
+    }
+    let transport: Arc<MemoryTransport> = Arc::new(MemoryTransport { bodies, ..MemoryTransport::default() });
+    let folder = tempfile::tempdir().unwrap();
+    let options: DownloadOptions = DownloadOptions::builder()
+        .base_url(BASE_URL)
+        .api_flavor(ApiFlavor::Zenodo)
+        .target_folder(folder.path().to_str().unwrap())
+        .token("secret")
+        .quiet(true)
+        .write_manifest(false)
+        .transport(transport.clone())
+        .build().unwrap();
+
+    let report: DownloadReport = download_record("30", &options).await.unwrap();
+
+    assert!(report.files.iter().all(|file| file.status == FileStatus::Downloaded), "{:?}", report);
+    assert_eq!(fs::read(folder.path().join("a.txt")).unwrap(), b"a\n");
+    assert_eq!(fs::read(folder.path().join("b.txt")).unwrap(), b"b\n");
+    let requests = transport.requests.lock().unwrap();
+    assert!(requests.iter().all(|(_, _, authorization)| authorization.as_deref() == Some("Bearer secret")));
+    let files: Vec<&str> = requests.iter()
+        .filter(|(method, _, _)| *method == "file")
+        .map(|(_, url, _)| url.rsplit('/').nth(1).unwrap())
+        .collect();
+    assert_eq!(files.len(), 2, "{:?}", requests);
+    assert!(files.contains(&"a.txt") && files.contains(&"b.txt"));
+}
+
+#[tokio::test]
+async fn transport_answers_and_failures_become_the_usual_errors()
+{
+    let missing: DownloadOptions = DownloadOptions::builder()
+        .base_url(BASE_URL)
+        .api_flavor(ApiFlavor::Zenodo)
+        .quiet(true)
+        .transport(Arc::new(MemoryTransport::default()))
+        .build().unwrap();
+    let err: ZenodoDlError = get_record_metadata("31", &missing).await.unwrap_err();
+    assert!(matches!(err, ZenodoDlError::NotFound { .. }), "{:?}", err);
+
+    let timing_out: DownloadOptions = DownloadOptions::builder()
+        .base_url(BASE_URL)
+        .api_flavor(ApiFlavor::Zenodo)
+        .quiet(true)
+        .retry_policy(fast_retries(0))
+        .transport(Arc::new(TimeoutTransport))
+        .build().unwrap();
+    let err: ZenodoDlError = get_record_metadata("31", &timing_out).await.unwrap_err();
+    assert!(matches!(err, ZenodoDlError::Timeout { .. }), "{:?}", err);
+    assert!(err.is_retryable());
+}
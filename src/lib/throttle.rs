@@ -0,0 +1,52 @@
+//! Bandwidth limit shared by all downloads of a run.
+
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+
+
+struct Bucket {
+    /// bytes that may be received without waiting, negative after a chunk
+    /// larger than the balance
+    tokens: f64,
+    refilled: Instant,
+}
+
+
+/// Token bucket refilled at `rate` bytes per second, holding at most one
+/// second worth of bytes
+pub(crate) struct RateLimiter {
+    /// bytes per second, 0 for unlimited
+    rate: u64,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(rate: u64) -> RateLimiter
+    {
+        return RateLimiter {
+            rate,
+            bucket: Mutex::new(Bucket { tokens: rate as f64, refilled: Instant::now() }),
+        };
+    }
+
+    /// Accounts for `bytes` received, waiting until they fit into the limit.
+    /// The lock is held while waiting, so concurrent downloads take turns.
+    pub(crate) async fn consume(&self, bytes: u64)
+    {
+        if self.rate == 0 {
+            return;
+        }
+        let rate: f64 = self.rate as f64;
+        let mut bucket = self.bucket.lock().await;
+        let now: Instant = Instant::now();
+        let refill: f64 = now.duration_since(bucket.refilled).as_secs_f64() * rate;
+        bucket.tokens = (bucket.tokens + refill).min(rate) - bytes as f64;
+        bucket.refilled = now;
+        if bucket.tokens < 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(-bucket.tokens / rate)).await;
+            bucket.tokens = 0.0;
+            bucket.refilled = Instant::now();
+        }
+    }
+}
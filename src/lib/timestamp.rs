@@ -0,0 +1,69 @@
+//! RFC 3339 timestamps as used in the file metadata, e.g.
+//! `2024-02-03T04:05:06.123456+00:00`.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64
+{
+    let year: i64 = if month <= 2 { year - 1 } else { year };
+    let era: i64 = year.div_euclid(400);
+    let year_of_era: i64 = year - era * 400;
+    let day_of_year: i64 = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era: i64 = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    return era * 146097 + day_of_era - 719468;
+}
+
+fn number(text: &str) -> Option<i64>
+{
+    if text.is_empty() || !text.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    return text.parse().ok();
+}
+
+/// Parses `YYYY-MM-DDTHH:MM:SS[.fraction](Z|±HH:MM)`. Returns `None` for
+/// anything else, including times before 1970.
+pub(crate) fn parse_rfc3339(raw: &str) -> Option<SystemTime>
+{
+    let raw: &str = raw.trim();
+    let (date, time) = raw.split_once(['T', 't', ' '])?;
+    let mut date_parts = date.splitn(3, '-');
+    let year: i64 = number(date_parts.next()?)?;
+    let month: i64 = number(date_parts.next()?)?;
+    let day: i64 = number(date_parts.next()?)?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let (clock, offset_secs): (&str, i64) = if let Some(clock) = time.strip_suffix(['Z', 'z']) {
+        (clock, 0)
+    } else {
+        let split_at: usize = time.rfind(['+', '-'])?;
+        let (clock, offset) = time.split_at(split_at);
+        let sign: i64 = if offset.starts_with('-') { -1 } else { 1 };
+        let (hours, minutes) = offset[1..].split_once(':')?;
+        (clock, sign * (number(hours)? * 3600 + number(minutes)? * 60))
+    };
+    let (clock, fraction): (&str, &str) = clock.split_once('.').unwrap_or((clock, ""));
+    let mut clock_parts = clock.splitn(3, ':');
+    let hour: i64 = number(clock_parts.next()?)?;
+    let minute: i64 = number(clock_parts.next()?)?;
+    let second: i64 = number(clock_parts.next()?)?;
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let nanos: u32 = if fraction.is_empty() {
+        0
+    } else {
+        number(fraction)?;
+        // nanosecond precision at most, shorter fractions padded
+        format!("{:0<9}", &fraction[..fraction.len().min(9)]).parse().ok()?
+    };
+
+    let secs: i64 = days_from_civil(year, month, day) * 86400
+        + hour * 3600 + minute * 60 + second - offset_secs;
+    let secs: u64 = u64::try_from(secs).ok()?;
+    return UNIX_EPOCH.checked_add(Duration::new(secs, nanos));
+}
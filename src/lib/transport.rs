@@ -0,0 +1,201 @@
+//! The HTTP requests of the library, sent through a replaceable transport,
+//! e.g. to serve them from memory in the tests of an application or from a
+//! cache.
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures_util::{stream, StreamExt, TryStreamExt};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::StatusCode;
+use thiserror::Error;
+
+use crate::redact_url;
+
+
+/// Body of a response as its chunks arrive
+pub type ResponseBody = BoxStream<'static, Result<Bytes, TransportError>>;
+
+/// What the methods of an `HttpTransport` return, e.g.
+/// `Box::pin(async move { ... })`
+pub type TransportFuture<'a> = BoxFuture<'a, Result<HttpResponse, TransportError>>;
+
+
+/// A request that could not be sent or a response that could not be read
+#[derive(Debug, Error)]
+pub enum TransportError {
+    /// error of the client of `ReqwestTransport`
+    #[error(transparent)]
+    Reqwest(reqwest::Error),
+
+    /// the server did not respond or stopped sending in time
+    #[error("{0}")]
+    Timeout(String),
+
+    /// any other failure, e.g. a refused or dropped connection
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl TransportError {
+    pub fn is_timeout(&self) -> bool
+    {
+        return match self {
+            TransportError::Reqwest(err) => err.is_timeout(),
+            TransportError::Timeout(_) => true,
+            TransportError::Failed(_) => false
+        };
+    }
+}
+
+
+/// A GET request of the library
+#[derive(Clone)]
+pub struct HttpRequest {
+    pub url: String,
+    /// e.g. `Authorization`, `Range` or `If-None-Match`
+    pub headers: Vec<(HeaderName, String)>,
+}
+
+impl HttpRequest {
+    pub fn get(url: &str) -> Self
+    {
+        return HttpRequest { url: url.to_string(), headers: Vec::new() };
+    }
+
+    pub fn header(mut self, name: HeaderName, value: impl Into<String>) -> Self
+    {
+        self.headers.push((name, value.into()));
+        return self;
+    }
+
+    /// Appends the parameters to the query of the URL; an unparsable URL
+    /// is kept as it is and fails when sent
+    pub fn query(mut self, parameters: &[(&str, &str)]) -> Self
+    {
+        if let Ok(mut url) = reqwest::Url::parse(&self.url) {
+            url.query_pairs_mut().extend_pairs(parameters);
+            self.url = url.to_string();
+        }
+        return self;
+    }
+
+    /// Value of the header `name`, if the request has it
+    pub fn header_value(&self, name: &HeaderName) -> Option<&str>
+    {
+        return self.headers.iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str());
+    }
+}
+
+
+/// Status, headers and body of the response to an `HttpRequest`
+pub struct HttpResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: ResponseBody,
+}
+
+impl HttpResponse {
+    /// Response with the whole body at once and its `Content-Length`
+    pub fn new(status: StatusCode, body: impl Into<Bytes>) -> Self
+    {
+        let body: Bytes = body.into();
+        let mut headers: HeaderMap = HeaderMap::new();
+        headers.insert(reqwest::header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+        return HttpResponse {
+            status,
+            headers,
+            body: stream::once(async move { Ok(body) }).boxed(),
+        };
+    }
+
+    /// Length of the body as announced by the `Content-Length` header
+    pub fn content_length(&self) -> Option<u64>
+    {
+        return self.headers.get(reqwest::header::CONTENT_LENGTH)?.to_str().ok()?.parse().ok();
+    }
+
+    /// The whole body, once all of it arrived
+    pub async fn bytes(self) -> Result<Bytes, TransportError>
+    {
+        let chunks: Vec<Bytes> = self.body.try_collect().await?;
+        return Ok(Bytes::from(chunks.concat()));
+    }
+
+    /// The whole body as text, invalid UTF-8 replaced
+    pub async fn text(self) -> Result<String, TransportError>
+    {
+        let body: Bytes = self.bytes().await?;
+        return Ok(String::from_utf8_lossy(&body).into_owned());
+    }
+}
+
+
+/// Sends the requests of the library. `DownloadOptions::transport` replaces
+/// the default `ReqwestTransport`; all requests are GET requests, and
+/// statuses other than success are answered as they are rather than as
+/// errors.
+pub trait HttpTransport: Send + Sync {
+    /// Requests metadata such as a record, its file listing, search results
+    /// or a citation
+    fn get_metadata(&self, request: HttpRequest) -> TransportFuture<'_>;
+
+    /// Requests the content of a file, whose body is read as it arrives;
+    /// requests may ask for a `Range` of it
+    fn stream_file(&self, request: HttpRequest) -> TransportFuture<'_>;
+}
+
+
+/// Transport sending all requests with a `reqwest::Client`, the default
+#[derive(Clone)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+impl ReqwestTransport {
+    pub fn new(client: reqwest::Client) -> Self
+    {
+        return ReqwestTransport { client };
+    }
+
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError>
+    {
+        let mut builder = self.client.get(&request.url);
+        for (name, value) in request.headers.into_iter() {
+            let mut value: HeaderValue = HeaderValue::from_str(&value)
+                .map_err(|err| TransportError::Failed(format!("invalid {} header: {}", name, err)))?;
+            value.set_sensitive(name == reqwest::header::AUTHORIZATION);
+            builder = builder.header(name, value);
+        }
+        let resp: reqwest::Response = builder.send().await.map_err(reqwest_error)?;
+        return Ok(HttpResponse {
+            status: resp.status(),
+            headers: resp.headers().clone(),
+            body: resp.bytes_stream().map_err(reqwest_error).boxed(),
+        });
+    }
+}
+
+impl HttpTransport for ReqwestTransport {
+    fn get_metadata(&self, request: HttpRequest) -> TransportFuture<'_>
+    {
+        return Box::pin(self.send(request));
+    }
+
+    fn stream_file(&self, request: HttpRequest) -> TransportFuture<'_>
+    {
+        return Box::pin(self.send(request));
+    }
+}
+
+/// The error with the URL in it redacted, as it ends up in messages and
+/// reports
+fn reqwest_error(err: reqwest::Error) -> TransportError
+{
+    return TransportError::Reqwest(match err.url().cloned() {
+        Some(url) => err.with_url(redact_url(url)),
+        None => err
+    });
+}
@@ -0,0 +1,55 @@
+//! Results of checking local files against the checksums of a record.
+
+use serde::{Serialize, Deserialize};
+
+
+/// State of the local copy of a single file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocalFileStatus {
+    /// present and matching its checksum
+    Verified,
+    /// present, but the record has no usable checksum for it
+    Unverified,
+    /// not present in the target folder
+    Missing,
+    /// present, but not matching its checksum
+    Corrupt,
+}
+
+
+/// Verification result of a single file of the record
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LocalFile {
+    pub filename: String,
+    /// size according to the record metadata, or of the local file when
+    /// verifying a manifest
+    pub size: u64,
+    pub status: LocalFileStatus,
+    /// checksum from the record metadata, e.g. `md5:0cc1…`
+    pub expected_checksum: Option<String>,
+    /// digest of the local file, only set for corrupt files
+    pub actual_checksum: Option<String>,
+}
+
+
+/// Verification results of all selected files of a record
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct VerificationReport {
+    pub record_id: String,
+    pub files: Vec<LocalFile>,
+}
+
+impl VerificationReport {
+    pub fn count(&self, status: LocalFileStatus) -> usize
+    {
+        return self.files.iter().filter(|file| file.status == status).count();
+    }
+
+    /// Whether no file is missing or corrupt
+    pub fn is_complete(&self) -> bool
+    {
+        return self.count(LocalFileStatus::Missing) == 0
+            && self.count(LocalFileStatus::Corrupt) == 0;
+    }
+}